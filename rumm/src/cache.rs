@@ -0,0 +1,95 @@
+//! A cache of the results of script tactics
+use crate::tactics::TacticsResult;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The results of the script tactics already executed, successes as well as failures.
+/// The cache is shared among all the proofs of a script, and can be shared among threads.
+#[derive(Clone, Default)]
+pub struct ProofCache {
+    results: Arc<Mutex<HashMap<String, TacticsResult>>>,
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+}
+
+impl ProofCache {
+    /// Look up the result stored for the given key
+    pub fn get(&self, key: &str) -> Option<TacticsResult> {
+        let result = self.results.lock().unwrap().get(key).cloned();
+        match result {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    pub fn insert(&self, key: String, result: TacticsResult) {
+        self.results.lock().unwrap().insert(key, result);
+    }
+
+    /// Forget the failures, which may no longer hold once new theorems can be used
+    pub fn clear_failures(&self) {
+        self.results.lock().unwrap().retain(|_, result| result.is_ok());
+    }
+
+    /// A summary of the cache usage
+    pub fn statistics(&self) -> String {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let ratio = if hits + misses > 0 { 100 * hits / (hits + misses) } else { 0 };
+        format!(
+            "Proof cache: {} hits, {} misses ({}% hit rate), {} entries",
+            hits,
+            misses,
+            ratio,
+            self.results.lock().unwrap().len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProofCache;
+    use crate::context::Settings;
+    use crate::lang::ProofStep;
+    use crate::script::Script;
+    use crate::test_support::{prove, script, statement, TempPath};
+
+    /// A script using the given cache
+    fn cached_script(text: &str, cache: &ProofCache) -> (Script, TempPath) {
+        let (mut script, file) = script(text);
+        script.set_settings(Settings { cache: Some(cache.clone()), ..Settings::default() });
+        (script, file)
+    }
+
+    fn mmp(script: &Script, theorem: &str, step: &ProofStep) -> String {
+        let mut mmp = vec![];
+        step.export_incomplete_mmp(statement(script, theorem).0, script.db(), &mut mmp).unwrap();
+        String::from_utf8(mmp).unwrap()
+    }
+
+    #[test]
+    fn hit() {
+        let cache = ProofCache::default();
+        let (script, _file) = cached_script("tactics hyp ( ) { try ! ? }", &cache);
+        let cold = prove(&script, "id", "{ use hyp }").unwrap();
+        let hit = prove(&script, "id", "{ use hyp }").unwrap();
+        assert_eq!(mmp(&script, "id", &hit), mmp(&script, "id", &cold));
+        assert_eq!(cache.statistics(), "Proof cache: 1 hits, 1 misses (50% hit rate), 1 entries");
+    }
+
+    #[test]
+    fn failures() {
+        let cache = ProofCache::default();
+        let (script, _file) = cached_script("tactics hyp ( ) !", &cache);
+        // `hyp` proves `ch`, and the second `!` then fails: the proof is cached, but not the failure
+        assert!(prove(&script, "bt", "{ apply ~ax-mp { use hyp } ! with ~wph $ ch $ }").is_err());
+        assert!(cache.statistics().ends_with(", 1 entries"));
+        assert!(prove(&script, "bt", "{ apply ~ax-mp { use hyp } ! with ~wph $ ch $ }").is_err());
+        assert!(cache.statistics().starts_with("Proof cache: 1 hits, 1 misses"));
+        // The failures of the script tactics itself are cached
+        assert!(prove(&script, "bt", "{ use hyp }").is_err());
+        assert!(cache.statistics().ends_with(", 2 entries"));
+    }
+}
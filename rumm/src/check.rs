@@ -0,0 +1,61 @@
+//! Static checking of scripts, before any proof is run
+use crate::error::{Error, Location};
+use crate::lang::{ParameterDefinition, TacticsDefinition, TacticsDict};
+
+/// The scope in which a part of a script is checked: the script tactics known,
+/// and the variables bound, which are the parameters of the enclosing tactics definition, if any.
+/// The problems found are collected, so that all of them can be reported at once.
+pub struct CheckScope<'a> {
+    tactics_definitions: &'a TacticsDict,
+    parameters: &'a [ParameterDefinition],
+    errors: Vec<Error>,
+}
+
+impl<'a> CheckScope<'a> {
+    pub fn new(tactics_definitions: &'a TacticsDict, parameters: &'a [ParameterDefinition]) -> Self {
+        CheckScope { tactics_definitions, parameters, errors: vec![] }
+    }
+
+    pub fn tactics_definition(&self, name: &str) -> Option<&'a TacticsDefinition> {
+        self.tactics_definitions.get(name.to_string())
+    }
+
+    pub fn error(&mut self, error: Error) {
+        self.errors.push(error);
+    }
+
+    /// Check that the variable is one of the parameters in scope.
+    /// Identifiers include their prefix, so this also checks the kind of the variable.
+    pub fn check_variable(&mut self, variable: &str, location: &Location) {
+        if !self.parameters.iter().any(|parameter| parameter.id() == variable) {
+            self.error(Error::UnboundVariable { location: location.clone(), variable: variable.to_string() });
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<Error> {
+        self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::test_support::script;
+
+    #[test]
+    fn script_errors() {
+        let (script, _file) = script("tactics hyp ( @T ) { try @T ! }\n\
+            tactics bad ( ) { try @U { use missing } }\n\
+            proof ~id { use hyp }\n\
+            proof ~bt { use hyp ~ax-mp }\n\
+            proof ~id { findhyp +F ! }\n");
+        let errors = script.check();
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(matches!(&errors[0], Error::UnboundVariable { variable, .. } if variable == "@U"));
+        assert!(matches!(&errors[1], Error::UnknownScriptTactics { name, .. } if name == "missing"));
+        assert!(matches!(&errors[2], Error::WrongParameterCount { name, expected: 1, found: 0, .. } if name == "hyp"));
+        assert!(matches!(&errors[3], Error::WrongParameterKind { name, index: 1, .. } if name == "hyp"));
+        assert!(matches!(&errors[4], Error::UnboundVariable { variable, .. } if variable == "+F"));
+        assert_eq!(errors[4].location().unwrap().line_number, 5);
+    }
+}
@@ -1,16 +1,106 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use crate::cache::ProofCache;
 use crate::tactics::Tactics;
 use crate::lang::ProofStep;
 use crate::lang::Db;
 use crate::lang::DisplayPair;
 use crate::lang::Hypotheses;
+use crate::lang::IndexedStatement;
 use crate::lang::TacticsDefinition;
 use crate::lang::TacticsDict;
 use core::fmt::{Debug, Formatter};
-use metamath_rs::formula::Substitutions;
+use crate::tactics::{TacticsError, TacticsResult};
+use metamath_rs::formula::{Substitutions, TypeCode};
 use metamath_rs::Formula;
 use metamath_rs::Label;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::trace::TraceSettings;
+use log::Level;
+
+/// The text of a tactics, as written in a script
+struct TacticsText<'a>(&'a dyn Tactics, &'a Db);
+
+impl core::fmt::Display for TacticsText<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
+        self.0.format(fmt, self.1)
+    }
+}
+
+/// Settings shared by all the proofs of a script
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// If set, enables backtracking, with the maximum number of times earlier choices are retried in each proof.
+    pub backtrack_limit: Option<usize>,
+    /// If set, the results of script tactics are cached and reused.
+    pub cache: Option<ProofCache>,
+    /// The number of proofs searched in parallel, or 0 to use all the available processors.
+    pub jobs: usize,
+    /// Whether the candidates of the `find`, `finddf` and `try` tactics are evaluated in parallel.
+    pub parallel_candidates: bool,
+    /// Whether searches may use any statement of the database, including the ones following the theorem being proved.
+    pub unordered: bool,
+    /// Whether searches may use the statements marked as "(New usage is discouraged.)"
+    pub allow_discouraged: bool,
+    /// The maximum number of goals the `auto` tactics considers, if not the default one
+    pub auto_budget: Option<usize>,
+    /// The level and format of the proof search traces
+    pub trace: TraceSettings,
+}
+
+/// Work variables stand for formulas which are not known yet when applying a theorem.
+/// They are bound later on, when unifying goals with hypotheses.
+/// Work variables are the variables declared in the database with a name starting with `&`,
+/// they are allocated starting from the last declared one, so that the first ones stay available as metavariables for scripts.
+#[derive(Clone)]
+pub struct WorkVariables {
+    declared: Arc<Vec<(Label, TypeCode)>>,
+    allocated: Vec<Label>,
+    bindings: Substitutions,
+}
+
+impl WorkVariables {
+    pub fn new(db: &Db) -> Self {
+        WorkVariables {
+            declared: Arc::new(db.work_variables()),
+            allocated: vec![],
+            bindings: Substitutions::default(),
+        }
+    }
+
+    /// Allocate a new work variable of the given typecode
+    fn allocate(&mut self, typecode: TypeCode) -> Option<Label> {
+        let (label, _) = self.declared.iter().rev()
+            .find(|(label, tc)| *tc == typecode && !self.allocated.contains(label))?;
+        self.allocated.push(*label);
+        Some(*label)
+    }
+
+    fn is_allocated(&self, label: &Label) -> bool {
+        self.allocated.contains(label)
+    }
+
+    /// Bind work variables, keeping the bindings fully resolved
+    fn bind(&mut self, subst: &Substitutions) {
+        let mut bindings = Substitutions::default();
+        for (label, formula) in self.bindings.iter() {
+            bindings.insert(*label, formula.substitute(subst));
+        }
+        for (label, formula) in subst.iter() {
+            bindings.insert(*label, formula.substitute(&bindings));
+        }
+        self.bindings = bindings;
+    }
+
+    /// Whether no work variable is in use
+    pub fn is_empty(&self) -> bool {
+        self.allocated.is_empty()
+    }
+
+    pub fn bindings(&self) -> &Substitutions {
+        &self.bindings
+    }
+}
 
 #[derive(Clone)]
 pub struct Context {
@@ -24,6 +114,18 @@ pub struct Context {
     tactics_variables: HashMap<String, Arc<dyn Tactics>>,
     formula_variables: HashMap<String, Formula>,
     subst_variables: HashMap<String, Substitutions>,
+    work_variables: WorkVariables,
+    backtracking: Option<Arc<AtomicUsize>>,
+    cache: Option<ProofCache>,
+    log: Arc<Mutex<String>>,
+    trace: TraceSettings,
+    target: String,
+    parallel: bool,
+    statements_before: Option<usize>,
+    allow_discouraged: bool,
+    auto_budget: Option<usize>,
+    /// Where the contexts of the goals left unproven are recorded, in interactive sessions
+    holes: Option<Arc<Mutex<Vec<Context>>>>,
     depth: usize,
 }
 
@@ -34,13 +136,21 @@ impl Debug for Context {
 }
 
 impl<'a> Context {
+    /// Build the context to prove the given goal.
+    /// When a theorem is given, only the statements preceding it are used, unless the settings allow any statement.
     pub fn new(
         db: Db,
+        theorem: Option<Label>,
         goal: Formula,
         hypotheses: Hypotheses,
         tactics_definitions: TacticsDict,
+        settings: &Settings,
     ) -> Self {
         let subgoals = vec![];
+        let work_variables = WorkVariables::new(&db);
+        // Only the statements preceding the theorem are used, otherwise the proof could be circular
+        let statements_before = if settings.unordered { None } else { theorem.and_then(|theorem| db.statement_index().position(theorem)) };
+        let backtracking = settings.backtrack_limit.map(|limit| Arc::new(AtomicUsize::new(limit)));
         Context {
             db,
             goal,
@@ -52,75 +162,154 @@ impl<'a> Context {
             tactics_variables: HashMap::default(),
             formula_variables: HashMap::default(),
             subst_variables: HashMap::default(),
+            work_variables,
+            backtracking,
+            cache: settings.cache.clone(),
+            log: Arc::new(Mutex::new(String::new())),
+            trace: settings.trace,
+            target: "proof".to_string(),
+            parallel: settings.parallel_candidates,
+            statements_before,
+            allow_discouraged: settings.allow_discouraged,
+            auto_budget: settings.auto_budget,
+            holes: None,
             depth: 0,
         }
     }
 
+    /// Trace a message of the proof search, if its level is enabled
+    pub fn trace(&self, level: Level, message: &str) {
+        if self.trace.enabled(level) {
+            let line = self.trace.format(level, &self.target, self.depth, message);
+            self.log.lock().unwrap().push_str(&line);
+        }
+    }
+
+    /// Set the target of the traces, i.e. the name of the tactics being executed, returning the previous one
+    pub fn set_target(&mut self, target: String) -> String {
+        std::mem::replace(&mut self.target, target)
+    }
+
     pub fn message(&self, message: &str) {
-        println!("{:indent$}{message}", "", indent = self.depth);
+        self.trace(Level::Trace, message);
+    }
+
+    /// The messages logged so far during this proof
+    pub fn log(&self) -> String {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// A copy of this context tracing into a log of its own, to be appended later on with `append_log`
+    pub fn with_new_log(&self) -> Self {
+        let mut context = self.clone();
+        context.log = Arc::new(Mutex::new(String::new()));
+        context
+    }
+
+    pub fn append_log(&self, log: &str) {
+        self.log.lock().unwrap().push_str(log);
     }
 
     pub fn enter(&self, message: &str) {
-        self.message(&format!("Proving {}", DisplayPair(&self.goal, &self.db)));
-        self.message(&format!(">> {message}"));
+        if self.trace.enabled(Level::Debug) {
+            self.trace(Level::Debug, &format!("Proving {}", DisplayPair(&self.goal, &self.db)));
+            self.trace(Level::Debug, &format!(">> {message}"));
+        }
     }
 
     pub fn exit(&self, message: &str) {
-        self.message(&format!("<< {message}"));
+        if self.trace.enabled(Level::Debug) {
+            self.trace(Level::Debug, &format!("<< {message}"));
+        }
+    }
+
+    /// Record the contexts of the goals left unproven from now on, so that they can be proven later on
+    pub fn record_holes(&mut self) -> Arc<Mutex<Vec<Context>>> {
+        let holes = Arc::new(Mutex::new(vec![]));
+        self.holes = Some(holes.clone());
+        holes
+    }
+
+    /// Record the context of the goal, left unproven, if requested
+    pub fn hole(&self) {
+        if let Some(holes) = &self.holes {
+            holes.lock().unwrap().push(self.clone());
+        }
+    }
+
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    pub fn auto_budget(&self) -> Option<usize> {
+        self.auto_budget
+    }
+
+    pub fn is_backtracking(&self) -> bool {
+        self.backtracking.is_some()
+    }
+
+    /// Consume one backtracking step, i.e. allow to retry an earlier choice.
+    /// Returns false if backtracking is disabled, or if its limit has been reached.
+    pub fn backtrack(&self) -> bool {
+        match &self.backtracking {
+            Some(remaining) => remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn cache(&self) -> Option<&ProofCache> {
+        self.cache.as_ref()
+    }
+
+    /// A key identifying the result of the given script tactics in this context, for the proof cache.
+    /// Returns `None` if work variables are in use, since the result then also depends on their state.
+    /// Tactics parameters are identified by their text.
+    /// The statements searches may use are part of the key, so that results are only shared between the proofs using the same ones.
+    pub fn cache_key(&self, tactics_name: &str) -> Option<String> {
+        if !self.work_variables.is_empty() {
+            return None;
+        }
+        let mut key = format!("{}\n{:?}\n{}\n", tactics_name, self.statements_before, DisplayPair(&self.goal, &self.db));
+        for (label, hyp) in self.hypotheses.iter() {
+            key.push_str(&format!("{} {}\n", DisplayPair(label, &self.db), DisplayPair(hyp, &self.db)));
+        }
+        for (formula, _) in self.subgoals.iter() {
+            key.push_str(&format!("{}\n", DisplayPair(formula, &self.db)));
+        }
+        let mut variables: Vec<String> = self.label_variables.iter()
+            .map(|(id, label)| format!("{} {}", id, DisplayPair(label, &self.db)))
+            .chain(self.tactics_variables.iter().map(|(id, tactics)| format!("{} {}", id, TacticsText(tactics.as_ref(), &self.db))))
+            .chain(self.formula_variables.iter().map(|(id, formula)| format!("{} {}", id, DisplayPair(formula, &self.db))))
+            .chain(self.subst_variables.iter().map(|(id, subst)| format!("{} {}", id, DisplayPair(subst, &self.db))))
+            .chain(self.variables.iter().map(|(label, formula)| format!("{} {}", DisplayPair(label, &self.db), DisplayPair(formula, &self.db))))
+            .collect();
+        variables.sort();
+        key.push_str(&variables.join("\n"));
+        Some(key)
     }
 
     pub fn get_tactics_definition(&self, name: String) -> Option<&TacticsDefinition> {
         self.tactics_definitions.get(name)
     }
 
+    /// A copy of this context for a nested tactics, whose traces are indented one level deeper
+    fn nested(&self) -> Self {
+        Self { depth: self.depth + 1, ..self.clone() }
+    }
+
     pub fn with_goal(&self, goal: Formula) -> Self {
-        Self {
-            db: self.db.clone(),
-            goal,
-            hypotheses: self.hypotheses.clone(),
-            subgoals: self.subgoals.clone(),
-            tactics_definitions: self.tactics_definitions.clone(),
-            variables: self.variables.clone(),
-            label_variables: self.label_variables.clone(),
-            tactics_variables: self.tactics_variables.clone(),
-            formula_variables: self.formula_variables.clone(),
-            subst_variables: self.subst_variables.clone(),
-            depth: self.depth + 1,
-        }
+        Self { goal, ..self.nested() }
     }
 
     pub fn with_variables(&self, v: &Substitutions) -> Self {
-        let mut variables = self.variables.clone();
-        variables.extend(v);
-        Self {
-            db: self.db.clone(),
-            goal: self.goal.clone(),
-            hypotheses: self.hypotheses.clone(),
-            subgoals: self.subgoals.clone(),
-            tactics_definitions: self.tactics_definitions.clone(),
-            variables,
-            label_variables: self.label_variables.clone(),
-            tactics_variables: self.tactics_variables.clone(),
-            formula_variables: self.formula_variables.clone(),
-            subst_variables: self.subst_variables.clone(),
-            depth: self.depth + 1,
-        }
+        let mut context = self.nested();
+        context.variables.extend(v);
+        context
     }
 
     pub fn without_variables(&self) -> Self {
-        Self {
-            db: self.db.clone(),
-            goal: self.goal.clone(),
-            hypotheses: self.hypotheses.clone(),
-            subgoals: self.subgoals.clone(),
-            tactics_definitions: self.tactics_definitions.clone(),
-            variables: Substitutions::default(),
-            label_variables: self.label_variables.clone(),
-            tactics_variables: self.tactics_variables.clone(),
-            formula_variables: self.formula_variables.clone(),
-            subst_variables: self.subst_variables.clone(),
-            depth: self.depth + 1,
-        }
+        Self { variables: Substitutions::default(), ..self.nested() }
     }
 
     pub fn add_subgoal(&mut self, formula: Formula, step: ProofStep) {
@@ -152,6 +341,60 @@ impl<'a> Context {
         self.subst_variables.get(&id)
     }
 
+    /// Allocate a new work variable, to be used in place of the given variable
+    pub fn new_work_variable(&mut self, variable: Label) -> TacticsResult<Formula> {
+        let typecode = self.db.label_typecode(variable);
+        let label = self.work_variables.allocate(typecode).ok_or(TacticsError::NoWorkVariable(variable))?;
+        self.message(&format!("New work variable {} for {}", DisplayPair(&label, &self.db), DisplayPair(&variable, &self.db)));
+        Ok(self.db.variable_formula(label, typecode))
+    }
+
+    /// Attempt to unify the given pattern with a formula, binding only the work variables of the pattern.
+    /// Returns the bindings needed, if any.
+    pub fn unify_work_variables(&self, formula: &Formula, pattern: &Formula) -> Option<Substitutions> {
+        if self.work_variables.allocated.is_empty() { return None; }
+        let mut subst = Substitutions::new();
+        formula.unify(pattern, &mut subst).ok()?;
+        let bindings = self.work_variable_bindings(&subst);
+        if pattern.substitute(&bindings) == *formula { Some(bindings) } else { None }
+    }
+
+    /// The substitutions of the given ones which are for the work variables in use
+    pub fn work_variable_bindings(&self, subst: &Substitutions) -> Substitutions {
+        let mut bindings = Substitutions::new();
+        for (label, f) in subst.iter() {
+            if self.work_variables.is_allocated(label) {
+                bindings.insert(*label, f.clone());
+            }
+        }
+        bindings
+    }
+
+    /// Bind work variables, updating the goal accordingly
+    pub fn bind_work_variables(&mut self, subst: &Substitutions) {
+        for (label, formula) in subst.iter() {
+            self.message(&format!("Bound work variable {} to {}", DisplayPair(label, &self.db), DisplayPair(formula, &self.db)));
+        }
+        self.work_variables.bind(subst);
+        self.goal = self.goal.substitute(subst);
+    }
+
+    /// Take over the given work variables, typically allocated and bound in a sub-context once it has succeeded.
+    /// Tactics shall leave the work variables of their context untouched when they fail.
+    pub fn commit_work_variables(&mut self, work_variables: &WorkVariables) {
+        self.work_variables = work_variables.clone();
+        self.goal = self.goal.substitute(self.work_variables.bindings());
+    }
+
+    pub fn work_variables(&self) -> &WorkVariables {
+        &self.work_variables
+    }
+
+    /// Substitute the work variables already bound in the given formula
+    pub fn resolve(&self, formula: &Formula) -> Formula {
+        formula.substitute(self.work_variables.bindings())
+    }
+
     pub fn get_variable_label(&self, f: Formula) -> Option<Label> {
         f.get_by_path(&[])
     }
@@ -168,11 +411,28 @@ impl<'a> Context {
     pub fn hypotheses(&self) -> &Hypotheses {
         &self.hypotheses
     }
+    /// The label of the theorem of the given name
+    pub fn get_theorem_label(&self, name: &str) -> Option<Label> {
+        self.db.get_theorem_label(name.to_string())
+    }
+    /// Whether the given theorem was stated by the script, and may not be used yet
+    pub fn is_draft(&self, label: Label) -> bool {
+        self.db.is_draft(label)
+    }
     pub fn get_theorem_formulas(&self, label: Label) -> Option<(Formula, Hypotheses)> {
         self.db.get_theorem_formulas(label)
     }
-    pub fn statements(&self, filter: impl Fn(bool, &[u8]) -> bool) -> impl Iterator<Item = (Label, Formula, Hypotheses)> + '_ {
-        self.db.statements(filter)
+    /// The statements which may match the target, among the ones the current theorem may use
+    pub fn candidate_statements(&self, target: &Formula, filter: impl Fn(&IndexedStatement) -> bool) -> Vec<(Label, Formula, Hypotheses)> {
+        self.db.candidate_statements(target, |s| self.may_use(s) && filter(s), self.statements_before)
+    }
+    /// The statements the current theorem may use
+    pub fn statements(&self, filter: impl Fn(&IndexedStatement) -> bool) -> Vec<(Label, Formula, Hypotheses)> {
+        self.db.statements(|s| self.may_use(s) && filter(s), self.statements_before)
+    }
+    /// Whether searches may use the given statement: statements whose usage is discouraged are skipped, unless allowed
+    fn may_use(&self, statement: &IndexedStatement) -> bool {
+        self.allow_discouraged || !statement.usage_discouraged
     }
 //    pub fn debug_formula(&self, f: &Formula) -> String {
 //        self.db.debug_formula(f)
@@ -9,7 +9,7 @@ use typed_arena::Arena;
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Location {
     pub filename: String,
     pub line: String,
@@ -19,7 +19,7 @@ pub struct Location {
 }
 
 impl Location {
-    fn to_slice<'a>(&self, label: String, arena: &'a Arena<String>) -> Slice<'a> {
+    pub(crate) fn to_slice<'a>(&self, label: String, arena: &'a Arena<String>) -> Slice<'a> {
         Slice{
             source: arena.alloc(self.line.clone()),
             line_start: self.line_number,
@@ -51,12 +51,48 @@ pub enum Error {
     ParseError { location: Location, expected: String, found: Token },
     #[error("Unexpected end of file: expected {expected}")]
     UnexpectedEndOfFile { location: Location, expected: String },
+    #[error("Expected {expected}, found {found}, which is not a number")]
+    InvalidNumber { location: Location, expected: String, found: String },
     #[error("Unknown symbol {symbol}")]
     UnknownSymbol{ location: Location, symbol: String },
     #[error("Unknown label {label}")]
     UnknownLabel{ location: Location, label: String },
+    #[error("Label {label} already exists in the database")]
+    DuplicateLabel{ location: Location, label: String },
     #[error("Unknown tactics name {1}")]
     UnknownTacticsName(Location, String),
+    #[error("Could not read included file {filename}: {error}")]
+    IncludeIoError { location: Location, filename: String, error: std::io::Error },
+    #[error("While parsing included file {filename}")]
+    IncludeParseError { #[source] error: Box<Error>, location: Location, filename: String },
+    #[error("No database loaded, use the 'load' keyword to load one")]
+    NoDatabaseLoaded,
+    #[error("Circular inclusion of file {filename}")]
+    CircularInclude { location: Location, filename: String },
+    #[error("{0}")]
+    InvalidArgument(String),
+    #[error("Formatting {filename} would change its syntax tree, it is left untouched")]
+    FormatMismatch { filename: String },
+    #[error("Not formatted: {}", .0.join(", "))]
+    NotFormatted(Vec<String>),
+    #[error("Cannot start the proof threads: {0}")]
+    ThreadPool(#[source] rayon::ThreadPoolBuildError),
+    #[error("Theorem {name} could not be added to the database")]
+    TheoremNotAdded { location: Option<Location>, name: String },
+    #[error("Unknown script tactics {name}")]
+    UnknownScriptTactics { location: Location, name: String },
+    #[error("Tactics {name} expects {expected} parameters, {found} given")]
+    WrongParameterCount { location: Location, name: String, expected: usize, found: usize },
+    #[error("Parameter {index} of tactics {name} shall be a {expected}, found a {found}")]
+    WrongParameterKind { location: Location, name: String, index: usize, expected: &'static str, found: &'static str },
+    #[error("Unbound variable {variable}")]
+    UnboundVariable { location: Location, variable: String },
+    #[error("{0} problems found while checking the script")]
+    CheckFailed(usize),
+    #[error("Language server error: {0}")]
+    LspError(String),
+    #[error("{0} of {1} proofs failed")]
+    ProofsFailed(usize, usize),
 }
 
 impl Error {
@@ -68,29 +104,49 @@ impl Error {
         match self {
             Error::TacticsParameterParseError(e, _) => Some(&*e),
             Error::TacticsParseError(e, _) => Some(&*e),
+            Error::IncludeParseError { error, .. } => Some(&*error),
             _ => None,
         }
     }
 
-    fn location(&self) -> &Location {
+    pub(crate) fn location(&self) -> Option<&Location> {
         match self {
-            Error::IoError(_) => todo!(),
-            Error::DBError(_, _) => todo!(),
-            Error::DBParseError { location, .. } => location,
-            Error::MMLexerError { location } => location,
+            Error::IoError(_) => None,
+            Error::DBError(_, _) => None,
+            Error::NoDatabaseLoaded => None,
+            Error::InvalidArgument(_) => None,
+            Error::FormatMismatch { .. } => None,
+            Error::NotFormatted(_) => None,
+            Error::ThreadPool(_) => None,
+            Error::TheoremNotAdded { location, .. } => location.as_ref(),
+            Error::CheckFailed(_) => None,
+            Error::LspError(_) => None,
+            Error::ProofsFailed(_, _) => None,
+            Error::DBParseError { location, .. } => Some(location),
+            Error::MMLexerError { location } => Some(location),
             Error::TacticsParameterParseError(e, _) => e.location(),
             Error::TacticsParseError(e, _) => e.location(),
-            Error::ParseError { location, .. } => location,
-            Error::UnexpectedEndOfFile { location, .. } => location,
-            Error::UnknownSymbol { location, .. } => location,
-            Error::UnknownLabel { location, .. } => location,
-            Error::UnknownTacticsName(location, _) => location,
+            Error::ParseError { location, .. } => Some(location),
+            Error::UnexpectedEndOfFile { location, .. } => Some(location),
+            Error::InvalidNumber { location, .. } => Some(location),
+            Error::UnknownSymbol { location, .. } => Some(location),
+            Error::UnknownLabel { location, .. } => Some(location),
+            Error::DuplicateLabel { location, .. } => Some(location),
+            Error::UnknownTacticsName(location, _) => Some(location),
+            Error::IncludeIoError { location, .. } => Some(location),
+            Error::IncludeParseError { location, .. } => Some(location),
+            Error::CircularInclude { location, .. } => Some(location),
+            Error::UnknownScriptTactics { location, .. } => Some(location),
+            Error::WrongParameterCount { location, .. } => Some(location),
+            Error::WrongParameterKind { location, .. } => Some(location),
+            Error::UnboundVariable { location, .. } => Some(location),
         }
     }
 
     fn add_slice<'a>(&self, slices: &mut Vec<Slice<'a>>, arena: &'a Arena<String>) {
-        let location = self.location();
-        slices.push(location.to_slice(format!("{}",self), arena));
+        if let Some(location) = self.location() {
+            slices.push(location.to_slice(format!("{}",self), arena));
+        }
         self.caused_by().and_then(|e| Some(e.add_slice(slices, arena)));
     }
 
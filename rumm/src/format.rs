@@ -0,0 +1,316 @@
+//! Formatting of rumm scripts, keeping their comments
+//!
+//! Scripts are parsed, and printed back from their syntax tree, which keeps their comments.
+//! The printed script is then laid out: blocks `{ ... }` fitting on one line are written on one line,
+//! the other ones are split, with one nested block per line, indented with tabs.
+use crate::error::{Error, Result};
+use crate::lang::Display;
+use crate::parser::{Parser, Token};
+use crate::script::Script;
+use crate::tactics::TacticsRegistry;
+use std::fs;
+use std::sync::Arc;
+
+/// The width up to which a block is kept on one line
+const WIDTH: usize = 100;
+/// The width of an indentation tab, when measuring lines
+const TAB_WIDTH: usize = 4;
+/// The keywords starting a command, at the top level of a script
+const COMMANDS: [&str; 6] = ["load", "include", "import", "tactics", "proof", "lemma"];
+
+enum Element {
+    Word(String),
+    Formula(String),
+    /// A comment, which is `trailing` when it follows some code on the same line
+    Comment { text: String, trailing: bool },
+    BlankLine,
+    Block(Vec<Element>),
+}
+
+/// Add an element, preceded by the given number of line breaks in the source
+fn push(elements: &mut Vec<Element>, newlines: usize, element: Element) {
+    let trailing = newlines == 0 && !elements.is_empty();
+    if newlines >= 2 && !elements.is_empty() {
+        elements.push(Element::BlankLine);
+    }
+    elements.push(match element {
+        Element::Comment { text, .. } => Element::Comment { text, trailing },
+        element => element,
+    });
+}
+
+/// The number of line breaks between two tokens
+fn gap_newlines(gap: &str) -> usize {
+    gap.matches('\n').count()
+}
+
+/// Split a printed script into its elements
+fn parse_elements(filename: String, text: &str) -> Result<Vec<Element>> {
+    let mut parser = Parser::from_str(filename, text);
+    let mut stack: Vec<Vec<Element>> = vec![vec![]];
+    let mut last = 0;
+    while let Some(token) = parser.lexer.next() {
+        let span = parser.lexer.span();
+        let elements = stack.last_mut().unwrap();
+        let newlines = gap_newlines(&text[last..span.start]);
+        last = span.end;
+        match token {
+            Token::LexerError => return Err(parser.parse_error("A rumm token", token)),
+            Token::DescriptiveComment(comment) | Token::Comment(comment) => push(elements, newlines, Element::Comment { text: comment, trailing: false }),
+            Token::CurlyBracketOpen => {
+                if newlines >= 2 && !elements.is_empty() {
+                    elements.push(Element::BlankLine);
+                }
+                stack.push(vec![]);
+            },
+            Token::CurlyBracketClose => {
+                if stack.len() == 1 {
+                    return Err(parser.parse_error("A tactics or a command", token));
+                }
+                let block = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Element::Block(block));
+            },
+            Token::FormulaStart => {
+                // Math symbols are separated by spaces, up to the closing `$`
+                let mut symbols = vec![];
+                loop {
+                    let rest = text[last..].trim_start();
+                    if rest.is_empty() {
+                        return Err(parser.unexpected_end_of_file("The end of the formula '$'"));
+                    }
+                    let length = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    last = text.len() - rest.len() + length;
+                    match &rest[..length] {
+                        "$" => break,
+                        symbol => symbols.push(symbol),
+                    }
+                }
+                parser.lexer.bump(last - span.end);
+                let formula = if symbols.is_empty() { "$ $".to_string() } else { format!("$ {} $", symbols.join(" ")) };
+                push(elements, newlines, Element::Formula(formula));
+            },
+            _ => push(elements, newlines, Element::Word(parser.lexer.slice().to_string())),
+        }
+    }
+    let elements = stack.pop().unwrap();
+    if !stack.is_empty() {
+        return Err(parser.unexpected_end_of_file("A closing brace '}'"));
+    }
+    Ok(elements)
+}
+
+/// Whether the word is a tactics on its own, like the nested blocks
+fn is_tactics(word: &str) -> bool {
+    word == "!" || word == "?" || word.starts_with('@')
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    (0..indent).for_each(|_| out.push('\t'));
+}
+
+/// The block written on a single line, if it has no comments
+fn one_line(elements: &[Element]) -> Option<String> {
+    let mut items = vec![];
+    for element in elements {
+        match element {
+            Element::Word(word) | Element::Formula(word) => items.push(word.clone()),
+            Element::Block(children) => items.push(one_line(children)?),
+            Element::BlankLine => {},
+            Element::Comment { .. } => return None,
+        }
+    }
+    if items.is_empty() {
+        Some("{ }".to_string())
+    } else {
+        Some(format!("{{ {} }}", items.join(" ")))
+    }
+}
+
+fn block(elements: &[Element], indent: usize, out: &mut String) {
+    if let Some(line) = one_line(elements) {
+        if indent * TAB_WIDTH + line.len() <= WIDTH {
+            out.push_str(&line);
+            return;
+        }
+    }
+    out.push('{');
+    // The name of the tactics and its first simple parameters stay on the opening line
+    let mut rest = elements;
+    while let Some((Element::Word(word), tail)) = rest.split_first() {
+        if word == "with" || is_tactics(word) {
+            break;
+        }
+        out.push(' ');
+        out.push_str(word);
+        rest = tail;
+    }
+    lines(rest, indent + 1, out);
+    newline(indent, out);
+    out.push('}');
+}
+
+/// Write the content of a block: each tactics on its own line, and the other tokens grouped on lines between them.
+/// Substitutions, after `with`, start a new line.
+fn lines(elements: &[Element], indent: usize, out: &mut String) {
+    let mut in_line = false;
+    for element in elements {
+        match element {
+            Element::Word(word) if is_tactics(word) => {
+                newline(indent, out);
+                out.push_str(word);
+                in_line = false;
+            },
+            Element::Word(word) | Element::Formula(word) => {
+                if in_line && word != "with" {
+                    out.push(' ');
+                } else {
+                    newline(indent, out);
+                }
+                out.push_str(word);
+                in_line = true;
+            },
+            Element::Block(children) => {
+                newline(indent, out);
+                block(children, indent, out);
+                in_line = false;
+            },
+            Element::Comment { text, trailing: true } => {
+                out.push(' ');
+                out.push_str(text);
+                in_line = false;
+            },
+            Element::Comment { text, trailing: false } => {
+                newline(indent, out);
+                out.push_str(text);
+                in_line = false;
+            },
+            Element::BlankLine => {
+                out.push('\n');
+                in_line = false;
+            },
+        }
+    }
+}
+
+fn start_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Lay out a printed script: each command starts a line, and the tactics of definitions and proofs start on the following line
+fn layout(filename: String, text: &str) -> Result<String> {
+    let elements = parse_elements(filename, text)?;
+    let mut out = String::new();
+    for element in &elements {
+        match element {
+            Element::Word(word) if COMMANDS.contains(&word.as_str()) => {
+                start_line(&mut out);
+                out.push_str(word);
+            },
+            Element::Word(word) | Element::Formula(word) => {
+                if !out.is_empty() && !out.ends_with('\n') && !(word == ")" && out.ends_with('(')) {
+                    out.push(' ');
+                }
+                out.push_str(word);
+            },
+            Element::Block(children) => {
+                start_line(&mut out);
+                block(children, 0, &mut out);
+            },
+            Element::Comment { text, trailing: true } => {
+                out.push(' ');
+                out.push_str(text);
+            },
+            Element::Comment { text, trailing: false } => {
+                start_line(&mut out);
+                out.push_str(text);
+            },
+            Element::BlankLine => {
+                start_line(&mut out);
+                out.push('\n');
+            },
+        }
+    }
+    start_line(&mut out);
+    Ok(out)
+}
+
+/// Format a script, printing it back from its syntax tree.
+/// Its `load` command is executed, as formulas are parsed with the database.
+pub fn format(filename: String, text: &str, registry: &Arc<TacticsRegistry>) -> Result<String> {
+    let script = Script::from_str(filename.clone(), text, registry.clone())?;
+    let printed = script.to_string(script.db());
+    let out = layout(filename.clone(), &printed)?;
+    // The formatted script shall be parsed back into the same syntax tree
+    let formatted = Script::from_str_with_db(filename.clone(), &out, script.db().clone(), registry.clone())?;
+    if formatted.to_string(script.db()) != printed {
+        return Err(Error::FormatMismatch { filename });
+    }
+    Ok(out)
+}
+
+/// Format the given files in place, or only check that they are formatted
+pub fn run(filenames: &[&str], check: bool, registry: &Arc<TacticsRegistry>) -> Result {
+    let mut unformatted = vec![];
+    for filename in filenames {
+        let text = fs::read_to_string(filename)?;
+        let formatted = format(filename.to_string(), &text, registry)?;
+        if formatted != text {
+            if check {
+                unformatted.push(filename.to_string());
+            } else {
+                fs::write(filename, formatted)?;
+            }
+        }
+    }
+    if !unformatted.is_empty() {
+        return Err(Error::NotFormatted(unformatted));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::lang::Display;
+    use crate::script::Script;
+    use crate::tactics::TacticsRegistry;
+    use crate::test_support::{TempPath, DATABASE};
+    use std::fs;
+    use std::sync::Arc;
+
+    /// Parse a script, print it back, and check that parsing the printed script gives the same syntax tree
+    fn round_trip(filename: &str, text: &str, registry: &Arc<TacticsRegistry>) -> String {
+        let script = Script::from_str(filename.to_string(), text, registry.clone()).unwrap();
+        let printed = script.to_string(script.db());
+        let reparsed = Script::from_str_with_db(filename.to_string(), &printed, script.db().clone(), registry.clone()).unwrap();
+        assert_eq!(reparsed.to_string(script.db()), printed);
+        printed
+    }
+
+    #[test]
+    fn comments() {
+        let database = TempPath::file("database.mm", DATABASE);
+        let text = format!("// The database\nload \"{}\"\n\n/** Try the hypotheses */\ntactics hyp ( ) {{ try ! ? }} // or leave it\n\n\
+            /* A lemma */\nlemma th ( $ ps $ ) $ ( ph -> ps ) $ {{\n\t// first\n\ttry\n\t! // hypothesis\n\t?\n}}\n// The end\n", database.filename());
+        let registry = Arc::new(TacticsRegistry::default());
+        let printed = round_trip("<test>", &text, &registry);
+        for comment in ["// The database", "/** Try the hypotheses */", "// or leave it", "/* A lemma */", "// first", "// hypothesis", "// The end"] {
+            assert!(printed.contains(comment), "{} is missing from:\n{}", comment, printed);
+        }
+        let formatted = format("<test>".to_string(), &text, &registry).unwrap();
+        assert_eq!(format("<test>".to_string(), &formatted, &registry).unwrap(), formatted);
+    }
+
+    #[test]
+    #[ignore = "needs the set.mm database next to the repository"]
+    fn set_rmm() {
+        let registry = Arc::new(TacticsRegistry::default());
+        let text = fs::read_to_string("../set.rmm").unwrap();
+        round_trip("../set.rmm", &text, &registry);
+        let formatted = format("../set.rmm".to_string(), &text, &registry).unwrap();
+        assert_eq!(format("../set.rmm".to_string(), &formatted, &registry).unwrap(), formatted);
+    }
+}
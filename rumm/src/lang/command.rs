@@ -0,0 +1,17 @@
+use crate::lang::Comment;
+
+/// A command of a script file, in the order it is written, with the comments preceding it
+pub struct Command {
+    pub comments: Vec<Comment>,
+    pub kind: CommandKind,
+}
+
+pub enum CommandKind {
+    Load(String),
+    Include(String),
+    Import(String),
+    /// A tactics definition, by name
+    Tactics(String),
+    /// A proof or a lemma, by index in the proofs of the script
+    Proof(usize),
+}
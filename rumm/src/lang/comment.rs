@@ -0,0 +1,43 @@
+use core::fmt::Formatter;
+
+/// An ordinary comment of a script, `// ...` or `/* ... */`, kept so that scripts can be printed back with them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    /// Whether the comment follows some code on the same line
+    pub trailing: bool,
+}
+
+/// Write comments within a tactics: trailing ones stay on the current line, the other ones start a new line,
+/// and a line break ends them, so that the code following them is not commented out.
+pub fn format_comments(comments: &[Comment], fmt: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
+    for comment in comments {
+        fmt.write_str(if comment.trailing { " " } else { "\n" })?;
+        fmt.write_str(&comment.text)?;
+    }
+    if !comments.is_empty() {
+        fmt.write_str("\n")?;
+    }
+    Ok(())
+}
+
+/// Write the comments preceding a command of a script: trailing ones stay on the line of the previous command,
+/// the other ones start after the blank line separating commands.
+/// Returns whether such comments on their own lines were written.
+pub fn format_command_comments(comments: &[Comment], separate: bool, fmt: &mut Formatter) -> std::result::Result<bool, std::fmt::Error> {
+    let split = comments.iter().position(|c| !c.trailing).unwrap_or(comments.len());
+    for comment in &comments[..split] {
+        fmt.write_str(" ")?;
+        fmt.write_str(&comment.text)?;
+    }
+    if separate {
+        fmt.write_str("\n\n")?;
+    }
+    for (index, comment) in comments[split..].iter().enumerate() {
+        if index > 0 {
+            fmt.write_str(if comment.trailing { " " } else { "\n" })?;
+        }
+        fmt.write_str(&comment.text)?;
+    }
+    Ok(split < comments.len())
+}
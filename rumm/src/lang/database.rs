@@ -1,32 +1,89 @@
 use crate::error::{Error, Result};
 use crate::lang::Display;
+use crate::lang::{MmSource, MmVariable, ProofStep};
+use crate::lang::statement_index::{IndexedStatement, StatementIndex};
 use crate::tactics::{TacticsResult, TacticsError};
 
-use colored::*;
 use metamath_rs::grammar::FormulaToken;
-use core::cell::RefCell;
 use core::fmt::Formatter;
 use metamath_rs::as_str;
 use metamath_rs::database::DbOptions;
 use metamath_rs::diag::StmtParseError;
-use metamath_rs::formula::Substitutions;
-use metamath_rs::proof::ProofTreeArray;
-use metamath_rs::verify::ProofBuilder;
+use metamath_rs::formula::{FormulaBuilder, Substitutions, TypeCode};
+use metamath_rs::proof::{ProofStyle, ProofTreeArray, ProofTreePrinter};
+use metamath_rs::verify::{verify_one, ProofBuilder};
+use annotate_snippets::display_list::DisplayList;
 use metamath_rs::Database;
 use metamath_rs::Formula;
 use metamath_rs::Label;
 use metamath_rs::StatementType;
 use metamath_rs::Symbol;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ops::Deref;
 use std::io::Write;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 /// A type for representing theorem essential hypotheses: a label and the corresponding formula.
 pub type Hypotheses = Box<[(Label, Formula)]>;
 
+/// A theorem stated in a script rather than in the database, with the math symbols of its hypotheses and conclusion as written
+#[derive(Clone, Debug)]
+pub struct NewTheorem {
+    pub name: String,
+    pub hypotheses: Vec<String>,
+    pub conclusion: String,
+}
+
+impl NewTheorem {
+    /// The theorem in the Metamath format, in its own block with its hypotheses, and with the given proof.
+    /// The block declares the variables which are not active at the end of the database, and when the proof steps are given,
+    /// the variables only used by the proof and the disjoint variable conditions required by the theorems it applies.
+    pub fn to_mm(&self, db: &Db, proof: &str, step: Option<&ProofStep>) -> String {
+        let typecode = db.provable_typecode();
+        let mut variables = vec![];
+        let mut disjoint = BTreeSet::new();
+        for symbol in self.hypotheses.iter().chain(std::iter::once(&self.conclusion)).flat_map(|formula| formula.split_whitespace()) {
+            if db.variable_declaration(symbol).is_some() && !variables.iter().any(|variable| variable == symbol) {
+                variables.push(symbol.to_string());
+            }
+        }
+        if let Some(step) = step {
+            step.collect_variables(db, &mut variables, &mut disjoint);
+        }
+        let mut mm = String::from("${\n");
+        let mut floating = 0;
+        for variable in &variables {
+            let declaration = db.variable_declaration(variable).unwrap_or_default();
+            if !declaration.global {
+                mm.push_str(&format!("  $v {} $.\n", variable));
+            }
+            if let (false, Some(typecode)) = (declaration.global_floating, &declaration.typecode) {
+                floating += 1;
+                mm.push_str(&format!("  {}.v{} $f {} {} $.\n", self.name, floating, typecode, variable));
+            }
+        }
+        for (first, second) in disjoint {
+            mm.push_str(&format!("  $d {} {} $.\n", first, second));
+        }
+        for (index, hypothesis) in self.hypotheses.iter().enumerate() {
+            mm.push_str(&format!("  {}.{} $e {} {} $.\n", self.name, index + 1, typecode, hypothesis));
+        }
+        mm.push_str(&format!("  {} $p {} {} $=\n      {} $.\n$}}\n", self.name, typecode, self.conclusion, proof.trim()));
+        mm
+    }
+}
+
 #[derive(Clone)]
 pub struct Db {
-    intern: Arc<RefCell<Database>>,
+    intern: Arc<RwLock<Database>>,
+    filename: Arc<RwLock<Option<String>>>,
+    index: Arc<RwLock<Arc<StatementIndex>>>,
+    /// The theorems added at the end of the main database file, by name, the file being left untouched on the disk
+    additions: Arc<RwLock<Vec<(String, String)>>>,
+    /// The theorems added, which are not used by searches until they are published
+    drafts: Arc<RwLock<HashSet<Label>>>,
+    /// How the variables are declared in the source files, by name
+    variables: Arc<RwLock<HashMap<String, MmVariable>>>,
 }
 
 impl Default for Db {
@@ -37,46 +94,136 @@ impl Default for Db {
             ..Default::default()
         };
         Db {
-            intern: Arc::new(RefCell::new(Database::new(options))),
+            intern: Arc::new(RwLock::new(Database::new(options))),
+            filename: Arc::new(RwLock::new(None)),
+            index: Arc::new(RwLock::new(Arc::new(StatementIndex::default()))),
+            additions: Arc::new(RwLock::new(vec![])),
+            drafts: Arc::new(RwLock::new(HashSet::new())),
+            variables: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 }
 
 impl Db {
+    /// Load the given Metamath database
+    pub fn load(filename: &str) -> Result<Self> {
+        let mut db = Db::default();
+        db.parse(filename)?;
+        Ok(db)
+    }
+
     pub fn parse(&mut self, filename: &str) -> Result {
-        let mut database = self.intern.borrow_mut();
+        let mut database = self.intern.write().unwrap();
 
-        print!("Loading \"{}\" ... ", filename);
+        log::info!("Loading \"{}\"", filename);
         database.parse(filename.to_string(), Vec::new());
         let err = database.diag_notations();
         if !err.is_empty() {
             return Err(Error::DBError(filename.to_string(), err).into());
         }
-        println!("{}", "ok".green());
 
-        print!("Building \"{}\" grammar... ", filename);
+        log::info!("Building \"{}\" grammar", filename);
         database.grammar_pass();
         let gerr = database.diag_notations();
         if !gerr.is_empty() {
             return Err(Error::DBError(filename.to_string(), gerr).into());
         }
-        println!("{}", "ok".green());
 
-        print!("Parsing \"{}\"... ", filename);
+        log::info!("Parsing \"{}\"", filename);
         database.stmt_parse_pass();
         let gerr = database.diag_notations();
         if !gerr.is_empty() {
             return Err(Error::DBError(filename.to_string(), gerr).into());
         }
-        println!("{}", "ok".green());
-        //let testx = self.get_theorem_label("testx".to_string()).unwrap();
-        //let (_f, ..) = self.get_theorem_formulas(testx).unwrap();
-        //println!("TESTX {}", f.debug(self.database.borrow_mut().name_result()));
+        *self.filename.write().unwrap() = Some(filename.to_string());
+        drop(database);
+
+        log::info!("Indexing \"{}\"", filename);
+        let source = MmSource::load(filename).ok();
+        let index = self.build_index(source.as_ref());
+        *self.index.write().unwrap() = Arc::new(index);
+        *self.variables.write().unwrap() = source.map_or_else(HashMap::new, |source| source.variables().clone());
+        Ok(())
+    }
+
+    /// The name of the database file loaded, if any.
+    pub fn filename(&self) -> Option<String> {
+        self.filename.read().unwrap().clone()
+    }
+
+    /// Read the source files of the loaded database, in order to update them.
+    pub fn source(&self) -> Result<MmSource> {
+        let filename = self.filename().ok_or(Error::NoDatabaseLoaded)?;
+        Ok(MmSource::load(&filename)?)
+    }
+
+    /// Set the theorems added at the end of the database, without proof, and return their labels.
+    /// The theorems added before are dropped, and the database is only parsed again if the additions changed.
+    /// The theorems added are only used by searches once published.
+    pub fn set_additions(&self, theorems: &[NewTheorem]) -> Result<Vec<Label>> {
+        let additions: Vec<(String, String)> = theorems.iter().map(|theorem| (theorem.name.clone(), theorem.to_mm(self, "?", None))).collect();
+        let previous = std::mem::replace(&mut *self.additions.write().unwrap(), additions.clone());
+        if previous != additions {
+            if let Err(error) = self.reparse() {
+                *self.additions.write().unwrap() = previous;
+                self.reparse()?;
+                return Err(error);
+            }
+        }
+        let labels = theorems.iter().map(|theorem| self.added_label(&theorem.name)).collect::<Result<Vec<Label>>>()?;
+        *self.drafts.write().unwrap() = labels.iter().copied().collect();
+        Ok(labels)
+    }
+
+    fn added_label(&self, name: &str) -> Result<Label> {
+        self.get_theorem_label(name.to_string())
+            .ok_or_else(|| Error::TheoremNotAdded { location: None, name: name.to_string() })
+    }
+
+    /// Whether the theorem of the given name was added by a script rather than read from the database source
+    pub fn is_added(&self, name: &str) -> bool {
+        self.additions.read().unwrap().iter().any(|(added, _)| added == name)
+    }
+
+    /// Whether the theorem was added by a script, and is not used by searches
+    pub fn is_draft(&self, label: Label) -> bool {
+        self.drafts.read().unwrap().contains(&label)
+    }
+
+    /// Have searches use a theorem added by the script, once it is proven
+    pub fn publish(&self, label: Label) {
+        self.drafts.write().unwrap().remove(&label);
+    }
+
+    /// Parse the database again, with the statements added, and rebuild its index
+    fn reparse(&self) -> Result {
+        let filename = self.filename().ok_or(Error::NoDatabaseLoaded)?;
+        let mut source = self.source()?;
+        let additions = self.additions.read().unwrap();
+        source.append(&additions.iter().map(|(_, mm)| format!("\n{}", mm)).collect::<String>());
+        drop(additions);
+        let mut database = self.intern.write().unwrap();
+        database.parse(filename.clone(), source.edited_files());
+        database.grammar_pass();
+        database.stmt_parse_pass();
+        let err = database.diag_notations();
+        if !err.is_empty() {
+            return Err(Error::DBError(filename, err));
+        }
+        drop(database);
+        let index = self.build_index(Some(&source));
+        *self.index.write().unwrap() = Arc::new(index);
         Ok(())
     }
 
+    /// The typecode of provable statements, usually `|-`
+    pub fn provable_typecode(&self) -> String {
+        let database = self.intern.read().unwrap();
+        as_str(database.name_result().atom_name(database.grammar_result().provable_typecode())).to_string()
+    }
+
     pub fn get_symbol(&self, name: String) -> Option<Symbol> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         Some(database
             .name_result()
             .lookup_symbol(name.as_bytes())?
@@ -85,7 +232,7 @@ impl Db {
     }
 
     pub fn get_theorem_label(&self, name: String) -> Option<Label> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         Some(database
             .name_result()
             .lookup_label(name.as_bytes())?.atom
@@ -93,7 +240,7 @@ impl Db {
     }
 
     pub fn get_theorem_formulas(&self, label: Label) -> Option<(Formula, Hypotheses)> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         let sref = database.statement_by_label(label)?;
         let formula = database.stmt_parse_result().get_formula(&sref)?.clone();
         let frame = database.get_frame(label)?;
@@ -102,7 +249,7 @@ impl Db {
     }
 
     pub fn parse_formula(&self, symbols: Vec<std::result::Result<FormulaToken, StmtParseError>>) -> std::result::Result<Formula, StmtParseError> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         let grammar = database.grammar_result().clone();
         let nset = database.name_result();
         let convert_to_provable = false;
@@ -110,8 +257,13 @@ impl Db {
             .parse_formula(&mut symbols.into_iter(), &grammar.typecodes(), convert_to_provable, nset)
     }
 
+    pub fn label_typecode(&self, label: Label) -> TypeCode {
+        let database = self.intern.read().unwrap();
+        database.label_typecode(label)
+    }
+
     pub fn ensure_type(&self, fmla: Formula, label: Label) -> TacticsResult<Formula> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         let target_tc = database.label_typecode(label);
         let source_tc = fmla.get_typecode();
         if target_tc != source_tc {
@@ -123,28 +275,108 @@ impl Db {
     }
 
     pub fn debug_formula<'a>(&'a self, f: &'a Formula) {
-        let database = self.intern.borrow();
-        println!("{:?}", f.as_ref(&database));
+        let database = self.intern.read().unwrap();
+        log::debug!("{:?}", f.as_ref(&database));
     }
 
-    pub fn statements(&self, filter: impl Fn(bool, &[u8]) -> bool) -> impl Iterator<Item = (Label, Formula, Hypotheses)> + '_ {
-        let database = self.intern.borrow();
+    /// Build the index of all axioms and provable statements of the database.
+    /// The source files are read for the discouragement markers in the statements comments.
+    fn build_index(&self, source: Option<&MmSource>) -> StatementIndex {
+        let database = self.intern.read().unwrap();
         let nset = database.name_result().clone();
         let provable = database.grammar_result().provable_typecode();
-        database.statements().filter_map(move |sref| {
+        let mut variables = HashSet::new();
+        let mut labels = vec![];
+        for sref in database.statements() {
             match sref.statement_type() {
+                StatementType::Floating => {
+                    if let Some(label) = nset.lookup_label(sref.label()) {
+                        variables.insert(label.atom);
+                    }
+                },
                 StatementType::Axiom | StatementType::Provable => {
-                    let is_axiom = sref.statement_type() == StatementType::Axiom;
-                    let name = sref.label();
-                    if nset.get_atom(&sref.math_at(0)) == provable && filter(is_axiom, name) {
-                        let label = nset.lookup_label(name)?.atom;
-                        let (formula, hyps) = self.get_theorem_formulas(label)?;
-                        Some((label, formula, hyps))
-                    } else { None }
+                    if nset.get_atom(&sref.math_at(0)) == provable {
+                        if let Some(label) = nset.lookup_label(sref.label()) {
+                            labels.push((label.atom, sref.label().into(), sref.statement_type() == StatementType::Axiom));
+                        }
+                    }
                 },
-                _ => None,
+                _ => {},
+            }
+        }
+        // The database lock is released before getting the formulas, which locks it again
+        drop(database);
+        let statements = labels.into_iter().filter_map(|(label, name, is_axiom): (Label, Box<[u8]>, bool)| {
+            let (formula, hyps) = self.get_theorem_formulas(label)?;
+            let usage_discouraged = source.map_or(false, |source| source.is_usage_discouraged(as_str(&name)));
+            Some(IndexedStatement { label, name, is_axiom, usage_discouraged, formula, hyps })
+        }).collect();
+        StatementIndex::new(statements, variables)
+    }
+
+    /// How the given variable is declared in the database source, if it is a variable
+    pub fn variable_declaration(&self, name: &str) -> Option<MmVariable> {
+        self.variables.read().unwrap().get(name).cloned()
+    }
+
+    /// The names of the variables of the given formula, in order of first appearance
+    pub fn variable_names(&self, formula: &Formula) -> Vec<String> {
+        let variables = self.variables.read().unwrap();
+        let mut names: Vec<String> = vec![];
+        for symbol in formula.to_string(self).split_whitespace() {
+            if variables.contains_key(symbol) && !names.iter().any(|name| name == symbol) {
+                names.push(symbol.to_string());
             }
-        }).collect::<Vec<_>>().into_iter()
+        }
+        names
+    }
+
+    /// The mandatory disjoint variable conditions of the given theorem, as pairs of the floating hypotheses of its variables
+    pub fn disjoint_floating_hypotheses(&self, label: Label) -> Vec<(Label, Label)> {
+        let database = self.intern.read().unwrap();
+        let nset = database.name_result();
+        let frame = match database.get_frame(label) {
+            Some(frame) => frame,
+            None => return vec![],
+        };
+        let floating = |variable: Symbol| frame.floating()
+            .find(|hypothesis| database.statement_by_label(*hypothesis).map_or(false, |sref| nset.get_atom(&sref.math_at(1)) == variable));
+        frame.mandatory_dv.iter()
+            .filter_map(|&(first, second)| Some((floating(frame.var_list[first])?, floating(frame.var_list[second])?)))
+            .collect()
+    }
+
+    /// All math symbols declared in the database, constants and variables
+    pub fn math_symbols(&self) -> Vec<String> {
+        let database = self.intern.read().unwrap();
+        database.statements()
+            .filter(|sref| matches!(sref.statement_type(), StatementType::Constant | StatementType::Variable))
+            .flat_map(|sref| sref.math_iter().map(|token| as_str(&token).to_string()).collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// The index of the statements of the database
+    pub fn statement_index(&self) -> Arc<StatementIndex> {
+        self.index.read().unwrap().clone()
+    }
+
+    /// All axioms and provable statements accepted by the filter, in database order, only up to the given position if any
+    pub fn statements(&self, filter: impl Fn(&IndexedStatement) -> bool, before: Option<usize>) -> Vec<(Label, Formula, Hypotheses)> {
+        let drafts = self.drafts.read().unwrap();
+        self.statement_index().statements(before)
+            .filter(|s| !drafts.contains(&s.label) && filter(s))
+            .map(|s| (s.label, s.formula.clone(), s.hyps.clone()))
+            .collect()
+    }
+
+    /// The axioms and provable statements accepted by the filter, and whose conclusion may match the target,
+    /// in database order, only up to the given position if any
+    pub fn candidate_statements(&self, target: &Formula, filter: impl Fn(&IndexedStatement) -> bool, before: Option<usize>) -> Vec<(Label, Formula, Hypotheses)> {
+        let drafts = self.drafts.read().unwrap();
+        self.statement_index().candidates(target, before).into_iter()
+            .filter(|s| !drafts.contains(&s.label) && filter(s))
+            .map(|s| (s.label, s.formula.clone(), s.hyps.clone()))
+            .collect()
     }
 
     /// Add a hypothesis step to a proof array
@@ -155,7 +387,7 @@ impl Db {
         stack_buffer: &mut Vec<u8>,
         arr: &mut ProofTreeArray,
     ) -> Option<usize> {
-        let database = self.intern.borrow();
+        let database = self.intern.read().unwrap();
         let nset = database.name_result().clone();
         let token = nset.atom_name(label);
         let address = nset.lookup_label(token)?.address;
@@ -172,17 +404,15 @@ impl Db {
         substitutions: &Substitutions,
         stack_buffer: &mut Vec<u8>,
         arr: &mut ProofTreeArray,
-    ) -> Option<usize> {
-        let database = self.intern.borrow();
+    ) -> TacticsResult<usize> {
+        let database = self.intern.read().unwrap();
         let token = database.name_result().atom_name(label);
-        let address = database.name_result().lookup_label(token)?.address;
+        let address = database.name_result().lookup_label(token).ok_or(TacticsError::UnknownLabel(label, None))?.address;
         let range = formula.as_ref(&database).append_to_stack_buffer(stack_buffer);
-        let frame = database.get_frame(label)?;
+        let frame = database.get_frame(label).ok_or(TacticsError::UnknownLabel(label, None))?;
         let mut hyps = vec![];
-        for label in frame.floating() {
-            let formula = &substitutions.get(label).unwrap_or_else(|| {
-                panic!("While building proof using {}: No substitution for {}", as_str(token), as_str(database.name_result().atom_name(label)));
-            });
+        for variable in frame.floating() {
+            let formula = substitutions.get(variable).ok_or(TacticsError::MissingSubstitution(label, variable))?;
             let proof_tree_index = formula.as_ref(&database).build_syntax_proof::<usize, Vec<usize>>(
                 stack_buffer,
                 arr,
@@ -190,19 +420,103 @@ impl Db {
             hyps.push(proof_tree_index);
         }
         hyps.extend(mand_hyps);
-        Some(arr.build(address, hyps, stack_buffer, range))
+        Ok(arr.build(address, hyps, stack_buffer, range))
+    }
+
+    /// The floating hypotheses declaring work variables, i.e. variables whose name starts with `&`,
+    /// with their typecodes, in declaration order.
+    pub fn work_variables(&self) -> Vec<(Label, TypeCode)> {
+        let database = self.intern.read().unwrap();
+        let nset = database.name_result();
+        database.statements().filter_map(|sref| {
+            if sref.statement_type() == StatementType::Floating && sref.math_at(1).starts_with(b"&") {
+                let label = nset.lookup_label(sref.label())?.atom;
+                Some((label, database.label_typecode(label)))
+            } else { None }
+        }).collect()
+    }
+
+    /// The formula consisting only of the given variable
+    pub fn variable_formula(&self, label: Label, typecode: TypeCode) -> Formula {
+        let mut builder = FormulaBuilder::default();
+        builder.reduce(label, 0, 0, true);
+        builder.build(typecode)
+    }
+
+    /// The labels of the floating hypotheses of the given theorem
+    pub fn floating_hypotheses(&self, label: Label) -> Option<Vec<Label>> {
+        let database = self.intern.read().unwrap();
+        Some(database.get_frame(label)?.floating().collect())
+    }
+
+    /// Verify the proofs of the given statements with the metamath-rs verifier.
+    /// The source given is the one of the loaded database, with the theorems added and the proofs to verify.
+    /// The loaded database is parsed again with it, which is incremental: only the segments whose proofs changed are parsed again.
+    /// Returns the diagnostic found for each statement, by statement label, showing the proof step which fails.
+    pub fn verify(&self, source: &MmSource, labels: &[String]) -> Result<HashMap<String, String>> {
+        let filename = self.filename().ok_or(Error::NoDatabaseLoaded)?;
+        log::info!("Verifying \"{}\"", filename);
+        let mut database = self.intern.write().unwrap();
+        database.parse(filename.clone(), source.edited_files());
+        database.scope_pass();
+        let mut failures = vec![];
+        for label in labels {
+            if let Some(address) = database.name_result().lookup_label(label.as_bytes()).map(|label| label.address) {
+                let statement = database.parse_result().statement(address);
+                if let Err(diag) = verify_one(database.parse_result(), database.name_result(), database.scope_result(), &mut (), statement) {
+                    failures.push((label.clone(), address, diag));
+                }
+            }
+        }
+        let mut diagnostics = HashMap::new();
+        for (label, address, diag) in failures {
+            let rendered = database.render_diags(vec![(address, diag)], |snippet| DisplayList::from(snippet).to_string());
+            diagnostics.insert(label, rendered.join("\n"));
+        }
+        // Parsing drops the results of the grammar and statement passes, the statements being unchanged they are computed again
+        database.grammar_pass();
+        database.stmt_parse_pass();
+        let err = database.diag_notations();
+        if !err.is_empty() {
+            return Err(Error::DBError(filename, err));
+        }
+        Ok(diagnostics)
+    }
+
+    /// Format a proof in the compressed Metamath format, as found between `$=` and `$.`.
+    pub fn compressed_proof(&self, theorem: Label, arr: &ProofTreeArray) -> String {
+        self.format_proof(theorem, arr, ProofStyle::Compressed)
     }
 
-    pub fn export_mmp<W: Write>(&self, theorem: Label, arr: &ProofTreeArray, out: &mut W) {
-        let database = self.intern.borrow();
+    /// Format a proof in the normal Metamath format, where each step is given by its label
+    pub fn normal_proof(&self, theorem: Label, arr: &ProofTreeArray) -> String {
+        self.format_proof(theorem, arr, ProofStyle::Normal)
+    }
+
+    fn format_proof(&self, theorem: Label, arr: &ProofTreeArray, style: ProofStyle) -> String {
+        let database = self.intern.read().unwrap();
         let thm_label = database.name_result().atom_name(theorem);
-        database.export_mmp_proof_tree(thm_label, arr, out).unwrap();
+        ProofTreePrinter {
+            db: &database,
+            style,
+            thm_label,
+            arr,
+            initial_chr: 6,
+            indent: 6,
+            line_width: 79,
+        }.to_string()
+    }
+
+    pub fn export_mmp<W: Write + ?Sized>(&self, theorem: Label, arr: &ProofTreeArray, out: &mut W) {
+        let database = self.intern.read().unwrap();
+        let thm_label = database.name_result().atom_name(theorem);
+        database.export_mmp_proof_tree(thm_label, arr, &mut &mut *out).unwrap();
     }
 }
 
 impl Display for Symbol {
     fn format(&self, f: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        let database = db.intern.borrow();
+        let database = db.intern.read().unwrap();
         f.write_str(as_str(
             database.name_result().atom_name(*self),
         ))
@@ -219,14 +533,14 @@ impl Display for Symbol {
 
 impl Display for Formula {
     fn format(&self, f: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        let database = db.intern.borrow();
+        let database = db.intern.read().unwrap();
         std::fmt::Display::fmt(&self.as_ref(&database), f)
     }
 }
 
 impl Display for Substitutions {
     fn format(&self, f: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        let database = db.intern.borrow();
+        let database = db.intern.read().unwrap();
         std::fmt::Debug::fmt(&self.as_ref(&database), f)
     }
 }
@@ -247,4 +561,48 @@ impl Display for Box<Substitutions> {
     fn format(&self, f: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
         self.deref().format(f, db)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::NewTheorem;
+    use crate::test_support::{load_db, DATABASE};
+
+    /// A theorem using `ta`, which is not declared at the top level of the database
+    fn theorem(name: &str) -> NewTheorem {
+        NewTheorem { name: name.to_string(), hypotheses: vec!["ta".to_string()], conclusion: "( ph -> ta )".to_string() }
+    }
+
+    #[test]
+    fn to_mm() {
+        let (db, _file) = load_db(DATABASE);
+        assert_eq!(theorem("th").to_mm(&db, "?", None),
+            "${\n  $v ta $.\n  th.v1 $f wff ta $.\n  th.1 $e |- ta $.\n  th $p |- ( ph -> ta ) $=\n      ? $.\n$}\n");
+    }
+
+    #[test]
+    fn set_additions() {
+        let (db, _file) = load_db(DATABASE);
+        let labels = db.set_additions(&[theorem("th")]).unwrap();
+        assert_eq!(db.get_theorem_label("th".to_string()), Some(labels[0]));
+        assert!(db.is_added("th") && db.is_draft(labels[0]));
+        db.publish(labels[0]);
+        assert!(!db.is_draft(labels[0]));
+        // Setting the additions again drops the theorems which are not stated anymore
+        let labels = db.set_additions(&[theorem("th2")]).unwrap();
+        assert!(db.get_theorem_label("th".to_string()).is_none());
+        assert!(!db.is_added("th") && db.is_added("th2") && db.is_draft(labels[0]));
+    }
+
+    #[test]
+    fn verify() {
+        let (db, _file) = load_db(DATABASE);
+        let mut source = db.source().unwrap();
+        source.set_proof("id", "id.1");
+        assert!(db.verify(&source, &["id".to_string()]).unwrap().is_empty());
+        // A proof which does not prove the statement is rejected
+        source.set_proof("id", "wph");
+        assert!(db.verify(&source, &["id".to_string()]).unwrap().contains_key("id"));
+        // The database can still be used once verified
+        assert!(db.get_theorem_formulas(db.get_theorem_label("id".to_string()).unwrap()).is_some());
+    }
+}
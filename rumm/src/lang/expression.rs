@@ -1,49 +1,54 @@
 use crate::parser::FormulaOrSubstitutionListId;
-use crate::parser::OptionalTactics;
 use std::sync::Arc;
 use crate::tactics::Tactics;
-use crate::error::Result;
+use crate::error::{Location, Result};
 use crate::parser::Parser;
 use crate::parser::Parse;
 use crate::parser::Token;
-use crate::tactics::{TacticsError, TacticsResult};
+use crate::tactics::{Continuation, Failure, TacticsError, TacticsResult};
+use crate::check::CheckScope;
 use crate::context::Context;
 use metamath_rs::Formula;
 use metamath_rs::Label;
 use metamath_rs::formula::Substitutions;
-use crate::lang::{Db, Display};
+use crate::lang::{Comment, Db, Display, DisplayPair, format_comments};
 use core::fmt::Formatter;
 
+/// Write a formula in the rumm syntax, within dollar signs
+pub fn format_formula(formula: &Formula, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
+    fmt.write_fmt(format_args!("$ {} $", DisplayPair(formula, db).to_string().trim()))
+}
+
 /// An expression evaluating to a formula
 pub enum FormulaExpression {
     Goal,
     Formula(Formula),
-    Variable(String),
+    Variable(String, Location),
     Statement(StatementExpression),
     DirectSubstitution(Formula, Box<FormulaExpression>, Box<FormulaExpression>),
-    ListSubstitution(String, Box<FormulaExpression>),
+    ListSubstitution(String, Location, Box<FormulaExpression>),
 }
 
 impl Display for FormulaExpression {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
         match self {
             FormulaExpression::Goal => fmt.write_str("goal"),
-            FormulaExpression::Formula(f) => f.format(fmt, db),
-            FormulaExpression::Variable(id) => fmt.write_str(id),
+            FormulaExpression::Formula(f) => format_formula(f, fmt, db),
+            FormulaExpression::Variable(id, _) => fmt.write_str(id),
             FormulaExpression::Statement(l) => {
-                l.format(fmt, db)?;
-                fmt.write_str("statement")
+                fmt.write_str("statement ")?;
+                l.format(fmt, db)
             },
             FormulaExpression::DirectSubstitution(what, with, in_expr) => {
-                fmt.write_str("s/")?;
-                what.format(fmt, db)?;
-                fmt.write_str("/")?;
+                fmt.write_str("s/ ")?;
+                format_formula(what, fmt, db)?;
+                fmt.write_str(" / ")?;
                 with.format(fmt, db)?;
-                fmt.write_str("/")?;
+                fmt.write_str(" / ")?;
                 in_expr.format(fmt, db)
             }
-            FormulaExpression::ListSubstitution(id, in_expr) => {
-                fmt.write_fmt(format_args!("s/ *{id} /"))?;
+            FormulaExpression::ListSubstitution(id, _, in_expr) => {
+                fmt.write_fmt(format_args!("s/ {id} / "))?;
                 in_expr.format(fmt, db)
             }
         }
@@ -56,7 +61,7 @@ impl Parse for FormulaExpression {
             Some(Token::FormulaStart) => Ok(FormulaExpression::Formula(parser.parse_mm_formula()?)),
             Some(Token::GoalKeyword) => Ok(FormulaExpression::Goal),
             Some(Token::StatementKeyword) => Ok(FormulaExpression::Statement(StatementExpression::parse(parser)?)),
-    		Some(Token::FormulaIdentifier(id)) => Ok(FormulaExpression::Variable(id)),
+    		Some(Token::FormulaIdentifier(id)) => Ok(FormulaExpression::Variable(id, parser.location())),
             Some(Token::BeginSubstitutionKeyword) => {
                 match parser.parse_formula_or_substvar()? {
                     FormulaOrSubstitutionListId::Formula(substitute_what) => {
@@ -67,9 +72,10 @@ impl Parse for FormulaExpression {
                         Ok(FormulaExpression::DirectSubstitution(substitute_what, Box::new(substitute_with), Box::new(substitute_in)))
                     },
                     FormulaOrSubstitutionListId::SubstitutionListIdentifier(id) => {
+                        let location = parser.location();
                         parser.parse_token(Token::SubstitutionKeyword)?;
                         let substitute_in = parser.parse_formula_expression()?;
-                        Ok(FormulaExpression::ListSubstitution(id, Box::new(substitute_in)))
+                        Ok(FormulaExpression::ListSubstitution(id, location, Box::new(substitute_in)))
                     }
                 }
             },
@@ -87,59 +93,134 @@ impl Parse for FormulaExpression {
 impl FormulaExpression {
     pub fn evaluate(&self, context: &Context) -> TacticsResult<Formula> {
         match self {
-            FormulaExpression::Statement(e) => { let label = e.evaluate(context)?; Ok(context.get_theorem_formulas(label).ok_or(TacticsError::UnknownLabel(label))?.0) },
+            FormulaExpression::Statement(e) => { let label = e.evaluate(context)?; Ok(context.get_theorem_formulas(label).ok_or(TacticsError::UnknownLabel(label, Some(e.location().clone())))?.0) },
             FormulaExpression::Goal => Ok(context.goal().clone()),
             FormulaExpression::Formula(f) => Ok(f.clone()),
-            FormulaExpression::Variable(id) => context.get_formula_variable(id.to_string()).ok_or(TacticsError::UnknownFormulaVariable(id.to_string())),
+            FormulaExpression::Variable(id, location) => context.get_formula_variable(id.to_string()).ok_or(TacticsError::UnknownFormulaVariable(id.to_string(), location.clone())),
             FormulaExpression::DirectSubstitution(what, with, in_expr) => Ok(in_expr.evaluate(context)?.substitute(context.variables()).replace(&what.substitute(context.variables()), &with.evaluate(context)?.substitute(context.variables()))),
-            FormulaExpression::ListSubstitution(id, in_expr) => Ok(in_expr.evaluate(context)?.substitute(context.variables()).substitute(context.get_substitution_variable(id.to_string()).ok_or(TacticsError::UnknownSubstitutionVariable(id.to_string()))?))
+            FormulaExpression::ListSubstitution(id, location, in_expr) => Ok(in_expr.evaluate(context)?.substitute(context.variables()).substitute(context.get_substitution_variable(id.to_string()).ok_or(TacticsError::UnknownSubstitutionVariable(id.to_string(), location.clone()))?))
+        }
+    }
+
+    pub fn check(&self, scope: &mut CheckScope) {
+        match self {
+            FormulaExpression::Goal | FormulaExpression::Formula(_) => {},
+            FormulaExpression::Statement(e) => e.check(scope),
+            FormulaExpression::Variable(id, location) => scope.check_variable(id, location),
+            FormulaExpression::DirectSubstitution(_, with, in_expr) => { with.check(scope); in_expr.check(scope); },
+            FormulaExpression::ListSubstitution(id, location, in_expr) => { scope.check_variable(id, location); in_expr.check(scope); },
         }
     }
 }
 
 /// An expression evaluating to a tactics
 pub enum TacticsExpression {
-    Constant(Arc<dyn Tactics>),
-    Variable(String),
+    Constant(Arc<dyn Tactics>, Location),
+    Variable(String, Location),
+    /// A tactics with the comments preceding it, and the ones before its closing brace
+    Commented(Box<TacticsExpression>, Vec<Comment>, Vec<Comment>),
 }
 
 impl Display for TacticsExpression {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            TacticsExpression::Constant(t) => t.format(fmt, db),
-            TacticsExpression::Variable(id) => fmt.write_str(id),
+            TacticsExpression::Constant(t, _) => t.format(fmt, db),
+            TacticsExpression::Variable(id, _) => fmt.write_str(id),
+            TacticsExpression::Commented(t, comments, closing) => {
+                format_comments(comments, fmt)?;
+                let text = DisplayPair(t.as_ref(), db).to_string();
+                match text.strip_suffix('}') {
+                    Some(body) if !closing.is_empty() => {
+                        fmt.write_str(body.trim_end())?;
+                        format_comments(closing, fmt)?;
+                        fmt.write_str("}")
+                    },
+                    _ => fmt.write_str(&text),
+                }
+            },
         }
     }
 }
 
 impl TacticsExpression {
-	pub fn cst(tactics: Arc<dyn Tactics>) -> Result<OptionalTactics> {
-		Ok(OptionalTactics::Some(TacticsExpression::Constant(tactics)))
+	/// Where the tactics expression appears in the script
+	pub fn location(&self) -> &Location {
+		match self {
+			TacticsExpression::Constant(_, location) => location,
+			TacticsExpression::Variable(_, location) => location,
+			TacticsExpression::Commented(t, _, _) => t.location(),
+		}
 	}
 
 	pub fn evaluate(&self, context: &Context) -> TacticsResult<Arc<dyn Tactics>> {
 		match self {
-			TacticsExpression::Constant(t) => Ok(t.clone()),
-			TacticsExpression::Variable(id) => context.get_tactics_variable(id.to_string()).ok_or(TacticsError::UnknownTacticsVariable(id.to_string())),
+			TacticsExpression::Constant(t, _) => Ok(t.clone()),
+			TacticsExpression::Variable(id, location) => context.get_tactics_variable(id.to_string()).ok_or(TacticsError::UnknownTacticsVariable(id.to_string(), location.clone())),
+			TacticsExpression::Commented(t, _, _) => t.evaluate(context),
+		}
+	}
+
+	pub fn check(&self, scope: &mut CheckScope) {
+		match self {
+			TacticsExpression::Constant(t, _) => t.check(scope),
+			TacticsExpression::Variable(id, location) => scope.check_variable(id, location),
+			TacticsExpression::Commented(t, _, _) => t.check(scope),
 		}
 	}
 
+	/// Record the failure of the tactics on the current goal, as a level of the failure tree
+	fn failed(&self, tactics: &Arc<dyn Tactics>, context: &Context, cause: TacticsError) -> TacticsError {
+		TacticsError::Failed(Box::new(Failure {
+			tactics: tactics.get_name(),
+			goal: context.goal().clone(),
+			location: self.location().clone(),
+			cause,
+		}))
+	}
+
 	pub fn execute(&self, context: &mut Context) -> TacticsResult {
-		self.evaluate(&context)?.execute(context)
+		let tactics = self.evaluate(&context)?;
+		// Traces are targeted with the name of the tactics executed
+		let target = context.set_target(tactics.get_name());
+		let result = tactics.execute(context);
+		context.set_target(target);
+		result.map_err(|e| self.failed(&tactics, context, e))
+	}
+
+	pub fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+		let tactics = self.evaluate(&context)?;
+		let target = context.set_target(tactics.get_name());
+		// Failures of the continuation are those of the following goals, they are not recorded at this level
+		let mut continued = false;
+		let result = tactics.execute_then(context, &mut |step, sub_context| { continued = true; then(step, sub_context) });
+		context.set_target(target);
+		match result {
+			Err(e) if !continued => Err(self.failed(&tactics, context, e)),
+			result => result,
+		}
 	}
 }
 
 /// An expression evaluating to a statement
 pub enum StatementExpression {
-    Constant(Label),
-    Variable(String),
+    Constant(Label, Location),
+    /// A theorem stated earlier in the script, by name, which is only added to the database when the script is executed
+    New(String, Location),
+    Variable(String, Location),
 }
 
 impl Display for StatementExpression {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            StatementExpression::Constant(l) => l.format(fmt, db),
-            StatementExpression::Variable(id) => fmt.write_str(id),
+            StatementExpression::Constant(l, _) => {
+                fmt.write_str("~")?;
+                l.format(fmt, db)
+            },
+            StatementExpression::New(name, _) => {
+                fmt.write_str("~")?;
+                fmt.write_str(name)
+            },
+            StatementExpression::Variable(id, _) => fmt.write_str(id),
         }
     }
 }
@@ -147,8 +228,8 @@ impl Display for StatementExpression {
 impl Parse for StatementExpression {
     fn parse(parser: &mut Parser) -> Result<Self> {
     	match parser.next_token() {
-    		Some(Token::TheoremLabel(name)) => Ok(StatementExpression::Constant(parser.get_theorem_label(name)?)),
-    		Some(Token::TheoremIdentifier(id)) => Ok(StatementExpression::Variable(id)),
+    		Some(Token::TheoremLabel(name)) => parser.parse_statement_label(name),
+    		Some(Token::TheoremIdentifier(id)) => Ok(StatementExpression::Variable(id, parser.location())),
             Some(token) => Err(parser.parse_error("A statement expression", token).into()),
             None => Err(parser.unexpected_end_of_file("A statement expression").into()),
     	}
@@ -156,10 +237,31 @@ impl Parse for StatementExpression {
 }
 
 impl StatementExpression {
+	/// Where the statement expression appears in the script
+	pub fn location(&self) -> &Location {
+		match self {
+			StatementExpression::Constant(_, location) => location,
+			StatementExpression::New(_, location) => location,
+			StatementExpression::Variable(_, location) => location,
+		}
+	}
+
 	pub fn evaluate(&self, context: &Context) -> TacticsResult<Label> {
 		match self {
-			StatementExpression::Constant(l) => Ok(*l),
-			StatementExpression::Variable(id) => context.get_label_variable(id.to_string()).ok_or(TacticsError::UnknownLabelVariable(id.to_string())),
+			StatementExpression::Constant(l, _) => Ok(*l),
+			// Only the lemmas already proven may be used, the other new theorems stay drafts
+			StatementExpression::New(name, location) => match context.get_theorem_label(name) {
+				Some(label) if !context.is_draft(label) => Ok(label),
+				Some(_) => Err(TacticsError::UnprovenTheorem(name.clone(), location.clone())),
+				None => Err(TacticsError::UnknownTheorem(name.clone(), location.clone())),
+			},
+			StatementExpression::Variable(id, location) => context.get_label_variable(id.to_string()).ok_or(TacticsError::UnknownLabelVariable(id.to_string(), location.clone())),
+		}
+	}
+
+	pub fn check(&self, scope: &mut CheckScope) {
+		if let StatementExpression::Variable(id, location) = self {
+			scope.check_variable(id, location);
 		}
 	}
 }
@@ -172,19 +274,23 @@ pub struct SubstitutionListExpression {
 
 pub enum SubstitutionExpression {
     Constant((Label, FormulaExpression)),
-    Variable(String),
+    Variable(String, Location),
 }
 
 impl Display for SubstitutionListExpression {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        for subst in &self.list {
+        for (index, subst) in self.list.iter().enumerate() {
+            if index > 0 {
+                fmt.write_str(" ")?;
+            }
             match subst {
                 SubstitutionExpression::Constant((l, f)) => {
-                    l.format(fmt, db)?; 
-                    fmt.write_str(" ")?; 
+                    fmt.write_str("~")?;
+                    l.format(fmt, db)?;
+                    fmt.write_str(" ")?;
                     f.format(fmt, db)?;
                 },
-                SubstitutionExpression::Variable(id) => fmt.write_str(&id)?,
+                SubstitutionExpression::Variable(id, _) => fmt.write_str(&id)?,
             }
         }
         Ok(())
@@ -202,16 +308,29 @@ impl Parse for SubstitutionListExpression {
 }
 
 impl SubstitutionListExpression {
+	pub fn is_empty(&self) -> bool {
+		self.list.is_empty()
+	}
+
 	pub fn evaluate(&self, context: &Context) -> TacticsResult<Substitutions> {
         let mut subst = Substitutions::new();
         for s in &self.list {
             match s {
                 SubstitutionExpression::Constant((l, f)) => { subst.insert(*l, context.db.ensure_type(f.evaluate(context)?, *l)?); },
-                SubstitutionExpression::Variable(id) => { subst.extend(context.get_substitution_variable(id.to_string()).ok_or(TacticsError::UnknownSubstitutionVariable(id.to_string()))?); },
+                SubstitutionExpression::Variable(id, location) => { subst.extend(context.get_substitution_variable(id.to_string()).ok_or(TacticsError::UnknownSubstitutionVariable(id.to_string(), location.clone()))?); },
             }
         }
         TacticsResult::Ok(subst)
 	}
+
+	pub fn check(&self, scope: &mut CheckScope) {
+        for s in &self.list {
+            match s {
+                SubstitutionExpression::Constant((_, f)) => f.check(scope),
+                SubstitutionExpression::Variable(id, location) => scope.check_variable(id, location),
+            }
+        }
+	}
 }
 
 ///
@@ -222,8 +341,37 @@ pub enum Expression {
     SubstitutionList(SubstitutionListExpression),
 }
 
+impl Display for Expression {
+    fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Expression::Formula(e) => e.format(fmt, db),
+            Expression::Statement(e) => e.format(fmt, db),
+            Expression::Tactics(e) => e.format(fmt, db),
+            Expression::SubstitutionList(e) => {
+                fmt.write_str("with ")?;
+                e.format(fmt, db)
+            },
+        }
+    }
+}
+
 impl Expression {
-	pub fn tactics(tactics: Arc<dyn Tactics>) -> Result<Option<Expression>> {
-		Ok(Some(Expression::Tactics(TacticsExpression::Constant(tactics))))
+	/// The kind of the expression, for the user
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Expression::Formula(_) => "formula",
+			Expression::Statement(_) => "theorem",
+			Expression::Tactics(_) => "tactics",
+			Expression::SubstitutionList(_) => "substitution list",
+		}
+	}
+
+	pub fn check(&self, scope: &mut CheckScope) {
+		match self {
+			Expression::Formula(e) => e.check(scope),
+			Expression::Statement(e) => e.check(scope),
+			Expression::Tactics(e) => e.check(scope),
+			Expression::SubstitutionList(e) => e.check(scope),
+		}
 	}
 }
\ No newline at end of file
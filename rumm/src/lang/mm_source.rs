@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+/// A Metamath source file, as read from the disk.
+struct MmFile {
+    name: String,
+    text: String,
+    /// The length of the text read from the disk, text may be appended after it
+    length_on_disk: usize,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl MmFile {
+    fn is_modified(&self) -> bool {
+        !self.edits.is_empty() || self.text.len() != self.length_on_disk
+    }
+
+    /// The text of the file, with all pending edits applied.
+    fn edited_text(&self) -> String {
+        let mut edits: Vec<&(Range<usize>, String)> = self.edits.iter().collect();
+        edits.sort_by_key(|(range, _)| range.start);
+        let mut text = String::with_capacity(self.text.len());
+        let mut position = 0;
+        for (range, replacement) in edits {
+            text.push_str(&self.text[position..range.start]);
+            text.push_str(replacement);
+            position = range.end;
+        }
+        text.push_str(&self.text[position..]);
+        text
+    }
+}
+
+/// The location of an assertion in the Metamath source files.
+struct MmStatement {
+    file: usize,
+    proof: Option<Range<usize>>,
+    /// Whether the comment of the statement contains "(New usage is discouraged.)"
+    usage_discouraged: bool,
+    /// Whether the comment of the statement contains "(Proof modification is discouraged.)"
+    modification_discouraged: bool,
+}
+
+/// How a variable is declared in the Metamath source files.
+#[derive(Clone, Debug, Default)]
+pub struct MmVariable {
+    /// Whether the variable is declared by a `$v` statement outside of any block, and is therefore active at the end of the database
+    pub global: bool,
+    /// The typecode given to the variable by its last floating hypothesis
+    pub typecode: Option<String>,
+    /// Whether the variable has a floating hypothesis outside of any block
+    pub global_floating: bool,
+}
+
+/// The raw text of a Metamath database and the files it includes.
+/// This allows to modify the proofs of single statements, keeping the rest of the files byte-for-byte identical.
+pub struct MmSource {
+    files: Vec<MmFile>,
+    statements: HashMap<String, MmStatement>,
+    variables: HashMap<String, MmVariable>,
+}
+
+impl MmSource {
+    /// Read the given Metamath file, and all the files it includes.
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let mut source = MmSource {
+            files: vec![],
+            statements: HashMap::new(),
+            variables: HashMap::new(),
+        };
+        source.load_file(filename)?;
+        Ok(source)
+    }
+
+    fn load_file(&mut self, filename: &str) -> io::Result<()> {
+        if self.files.iter().any(|f| f.name == filename) {
+            return Ok(());
+        }
+        let text = fs::read_to_string(filename)?;
+        let includes = self.scan(self.files.len(), &text, 0);
+        self.files.push(MmFile { name: filename.to_string(), length_on_disk: text.len(), text, edits: vec![] });
+        for include in includes {
+            self.load_file(&include)?;
+        }
+        Ok(())
+    }
+
+    /// Register the assertions found in the given text, which starts at the given offset in the file.
+    /// Returns the files included.
+    fn scan(&mut self, file: usize, text: &str, offset: usize) -> Vec<String> {
+        let mut includes = vec![];
+        let mut tokens = Tokens::new(text);
+        let mut last_token = 0..0;
+        // The comment preceding the current statement, if any
+        let mut comment = None;
+        // The number of blocks opened and not closed yet
+        let mut depth = 0usize;
+        while let Some((token, range)) = tokens.next() {
+            match token {
+                "$(" => {
+                    tokens.skip_to("$)");
+                    comment = Some(range.start..tokens.position);
+                }
+                "$[" => {
+                    if let Some((include, _)) = tokens.next() { includes.push(include.to_string()); }
+                    tokens.skip_to("$]");
+                }
+                "$a" | "$p" => {
+                    let mut proof = None;
+                    while let Some((token, range)) = tokens.next() {
+                        match token {
+                            "$=" => { proof = Some(range.end..range.end); },
+                            "$." => {
+                                if let Some(proof) = &mut proof { proof.end = range.start; }
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                    let label = text[last_token.clone()].to_string();
+                    let comment = comment.take().map_or("", |comment: Range<usize>| &text[comment]);
+                    self.statements.insert(label, MmStatement {
+                        file,
+                        proof: proof.map(|proof| proof.start + offset..proof.end + offset),
+                        usage_discouraged: comment.contains("(New usage is discouraged.)"),
+                        modification_discouraged: comment.contains("(Proof modification is discouraged.)"),
+                    });
+                }
+                "$v" => {
+                    while let Some((variable, _)) = tokens.next() {
+                        if variable == "$." { break; }
+                        self.variables.entry(variable.to_string()).or_default().global |= depth == 0;
+                    }
+                    comment = None;
+                }
+                "$f" => {
+                    if let (Some((typecode, _)), Some((variable, _))) = (tokens.next(), tokens.next()) {
+                        let declaration = self.variables.entry(variable.to_string()).or_default();
+                        declaration.typecode = Some(typecode.to_string());
+                        declaration.global_floating |= depth == 0;
+                    }
+                    tokens.skip_to("$.");
+                    comment = None;
+                }
+                "$e" | "$c" | "$d" => {
+                    tokens.skip_to("$.");
+                    comment = None;
+                }
+                "${" => {
+                    depth += 1;
+                    comment = None;
+                }
+                "$}" => {
+                    depth = depth.saturating_sub(1);
+                    comment = None;
+                }
+                _ => {}
+            }
+            last_token = range;
+        }
+        includes
+    }
+
+    /// Append statements at the end of the main file, so that their proofs can be set like the other ones
+    pub fn append(&mut self, text: &str) {
+        let offset = self.files[0].text.len();
+        self.files[0].text.push_str(text);
+        self.scan(0, text, offset);
+    }
+
+    /// Whether new usage of the given statement is discouraged
+    pub fn is_usage_discouraged(&self, label: &str) -> bool {
+        self.statements.get(label).map_or(false, |statement| statement.usage_discouraged)
+    }
+
+    /// The declarations of all the variables of the database, by name
+    pub fn variables(&self) -> &HashMap<String, MmVariable> {
+        &self.variables
+    }
+
+    /// Whether modifying the proof of the given statement is discouraged
+    pub fn is_modification_discouraged(&self, label: &str) -> bool {
+        self.statements.get(label).map_or(false, |statement| statement.modification_discouraged)
+    }
+
+    /// Replace the proof of the given provable statement.
+    /// Returns false if the statement was not found in the source files.
+    pub fn set_proof(&mut self, label: &str, proof: &str) -> bool {
+        if let Some(MmStatement { file, proof: Some(range), .. }) = self.statements.get(label) {
+            let replacement = format!("\n      {}\n      ", proof.trim());
+            let edits = &mut self.files[*file].edits;
+            // The same statement may be proven several times, the last proof replaces the previous ones
+            match edits.iter_mut().find(|(edited, _)| edited == range) {
+                Some(edit) => edit.1 = replacement,
+                None => edits.push((range.clone(), replacement)),
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The names and edited contents of all modified files.
+    pub fn edited_files(&self) -> Vec<(String, Vec<u8>)> {
+        self.files.iter()
+            .filter(|file| file.is_modified())
+            .map(|file| (file.name.clone(), file.edited_text().into_bytes()))
+            .collect()
+    }
+
+    /// Write back the modified files.
+    /// If an output file name is given, the main database file is written there instead, and the original files are left untouched.
+    pub fn write(&self, output: Option<&str>) -> io::Result<()> {
+        for (index, file) in self.files.iter().enumerate() {
+            if !file.is_modified() { continue; }
+            match output {
+                None => {
+                    log::info!("Writing \"{}\"", file.name);
+                    fs::write(&file.name, file.edited_text())?;
+                }
+                Some(output) if index == 0 => {
+                    log::info!("Writing \"{}\"", output);
+                    fs::write(output, file.edited_text())?;
+                }
+                Some(_) => {
+                    log::warn!("Proofs in included file \"{}\" are not written to the output copy", file.name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A simple iterator over the white-space separated tokens of a Metamath file, with their byte ranges.
+struct Tokens<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokens { text, position: 0 }
+    }
+
+    fn skip_to(&mut self, end: &str) {
+        while let Some((token, _)) = self.next() {
+            if token == end { return; }
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (&'a str, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.text.as_bytes();
+        while self.position < bytes.len() && bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if self.position >= bytes.len() { return None; }
+        let start = self.position;
+        while self.position < bytes.len() && !bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        Some((&self.text[start..self.position], start..self.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MmSource;
+    use crate::test_support::{TempPath, DATABASE};
+
+    /// Load a database from a file of the temporary directory
+    fn load(text: &str) -> MmSource {
+        let file = TempPath::file("source.mm", text);
+        MmSource::load(&file.filename()).unwrap()
+    }
+
+    fn edited_text(source: &MmSource) -> String {
+        String::from_utf8(source.edited_files().remove(0).1).unwrap()
+    }
+
+    /// The test database, with the given proof of `id`
+    fn with_id_proof(proof: &str) -> String {
+        DATABASE.replace("id $p |- ph $= ? $.", &format!("id $p |- ph $=\n      {}\n      $.", proof))
+    }
+
+    #[test]
+    fn unmodified() {
+        let source = load(DATABASE);
+        assert!(source.edited_files().is_empty());
+    }
+
+    #[test]
+    fn set_proof() {
+        let mut source = load(DATABASE);
+        assert!(source.set_proof("id", "( id.1 )"));
+        assert!(!source.set_proof("unknown", "( id.1 )"));
+        assert_eq!(edited_text(&source), with_id_proof("( id.1 )"));
+    }
+
+    #[test]
+    fn set_proof_twice() {
+        let mut source = load(DATABASE);
+        assert!(source.set_proof("id", "?"));
+        assert!(source.set_proof("id", "( id.1 )"));
+        assert_eq!(edited_text(&source), with_id_proof("( id.1 )"));
+    }
+
+    #[test]
+    fn discouraged() {
+        let text = DATABASE.replace("  id $p", "  $( (New usage is discouraged.) (Proof modification is discouraged.) $)\n  id $p");
+        let source = load(&text);
+        assert!(source.is_usage_discouraged("id"));
+        assert!(source.is_modification_discouraged("id"));
+        assert!(!source.is_usage_discouraged("wph"));
+    }
+
+    #[test]
+    fn append() {
+        let mut source = load(DATABASE);
+        let addition = "id2 $p |- ph $= ? $.\n";
+        source.append(addition);
+        assert!(source.set_proof("id2", "( id )"));
+        assert_eq!(edited_text(&source), format!("{}{}", DATABASE, addition.replace("$= ? $.", "$=\n      ( id )\n      $.")));
+    }
+
+    #[test]
+    fn variables() {
+        let source = load(DATABASE);
+        let ph = &source.variables()["ph"];
+        assert!(ph.global && ph.global_floating);
+        assert_eq!(ph.typecode.as_deref(), Some("wff"));
+        // `ta` is only declared within a closed block
+        let ta = &source.variables()["ta"];
+        assert!(!ta.global && !ta.global_floating);
+        assert_eq!(ta.typecode.as_deref(), Some("wff"));
+    }
+}
@@ -1,23 +1,33 @@
 //! A Metamath Proof Assistant
+mod command;
+mod comment;
 mod database;
 mod display;
 mod expression;
+mod mm_source;
 mod parameter_definition;
 mod proof_definition;
+mod statement_index;
 mod tactics_definition;
 
+pub use command::{Command, CommandKind};
+pub use comment::{Comment, format_comments, format_command_comments};
 pub use database::Db;
 pub use database::Hypotheses;
+pub use database::NewTheorem;
 pub use display::Display;
 pub use display::DisplayPair;
 pub use expression::Expression;
+pub use expression::format_formula;
 pub use expression::FormulaExpression;
 pub use expression::StatementExpression;
 pub use expression::TacticsExpression;
 pub use expression::SubstitutionExpression;
 pub use expression::SubstitutionListExpression;
+pub use mm_source::{MmSource, MmVariable};
 pub use parameter_definition::ParameterDefinition;
 pub use proof_definition::ProofDefinition;
 pub use proof_definition::ProofStep;
+pub use statement_index::IndexedStatement;
 pub use tactics_definition::TacticsDefinition;
 pub use tactics_definition::TacticsDict;
@@ -1,5 +1,6 @@
 
 use crate::error::Result;
+use crate::lang::Expression;
 use crate::parser::{Parse, Parser, Token};
 
 #[derive(Debug)]
@@ -23,7 +24,47 @@ impl Parse for ParameterDefinition {
     }
 }
 
+impl std::fmt::Display for ParameterDefinition {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParameterDefinition::SubstitutionList(id) => write!(fmt, "with {}", id),
+            _ => fmt.write_str(self.id()),
+        }
+    }
+}
+
 impl ParameterDefinition {
+    /// The identifier of the parameter, including its prefix
+    pub fn id(&self) -> &str {
+        match self {
+            ParameterDefinition::Tactics(id) => id,
+            ParameterDefinition::Theorem(id) => id,
+            ParameterDefinition::Formula(id) => id,
+            ParameterDefinition::SubstitutionList(id) => id,
+        }
+    }
+
+    /// The kind of the parameter, for the user
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParameterDefinition::Tactics(_) => "tactics",
+            ParameterDefinition::Theorem(_) => "theorem",
+            ParameterDefinition::Formula(_) => "formula",
+            ParameterDefinition::SubstitutionList(_) => "substitution list",
+        }
+    }
+
+    /// Whether the given expression can be passed for this parameter
+    pub fn accepts(&self, expression: &Expression) -> bool {
+        matches!(
+            (expression, self),
+            (Expression::Tactics(_), ParameterDefinition::Tactics(_))
+                | (Expression::Statement(_), ParameterDefinition::Theorem(_))
+                | (Expression::Formula(_), ParameterDefinition::Formula(_))
+                | (Expression::SubstitutionList(_), ParameterDefinition::SubstitutionList(_))
+        )
+    }
+
     fn parse_substitution_parameter_definition(parser: &mut Parser) -> Result<Self> {
         match parser.next_token() {
             Some(Token::SubstitutionListIdentifier(id)) => Ok(ParameterDefinition::SubstitutionList(id)),
@@ -1,11 +1,16 @@
 use crate::lang::TacticsExpression;
-use crate::context::Context;
-use crate::error::Result;
+use crate::check::CheckScope;
+use crate::context::{Context, Settings};
+use log::Level;
+use crate::error::{Error, Location, Result};
 use crate::lang::TacticsDict;
-use crate::lang::{Db, Display};
-use crate::parser::{Parse, Parser};
-use crate::tactics::TacticsError;
+use crate::lang::{Db, Display, DisplayPair, NewTheorem};
+use crate::parser::{Parse, Parser, Token};
+use crate::tactics::{TacticsError, TacticsResult};
 use core::fmt::Formatter;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::io::Write;
 use metamath_rs::formula::Substitutions;
 use metamath_rs::proof::ProofTreeArray;
 use metamath_rs::Formula;
@@ -24,6 +29,9 @@ pub enum ProofStep {
 		label: Label,
 		result: Formula,
 	},
+	Skipped {
+		result: Formula,
+	},
 }
 
 impl ProofStep {
@@ -45,10 +53,82 @@ impl ProofStep {
     	ProofStep::Hyp { label, result }
     }
 
+    /// A step for a goal left unproven
+    pub fn skipped(result: Formula) -> Self {
+    	ProofStep::Skipped { result }
+    }
+
     pub fn result(&self) -> &Formula {
         match self {
             ProofStep::Apply { result: r, .. } => r,
             ProofStep::Hyp { result: r, .. } => r,
+            ProofStep::Skipped { result: r } => r,
+        }
+    }
+
+    /// Substitute the given variables in all the formulas of this proof
+    pub fn substitute(&self, subst: &Substitutions) -> Self {
+        match self {
+            ProofStep::Apply { apply, apply_on, result, substitutions } => {
+                let mut new_substitutions = Substitutions::new();
+                for (label, formula) in substitutions.iter() {
+                    new_substitutions.insert(*label, formula.substitute(subst));
+                }
+                ProofStep::apply(
+                    *apply,
+                    apply_on.iter().map(|step| step.substitute(subst)).collect(),
+                    result.substitute(subst),
+                    Box::new(new_substitutions),
+                )
+            },
+            ProofStep::Hyp { label, result } => ProofStep::hyp(*label, result.substitute(subst)),
+            ProofStep::Skipped { result } => ProofStep::skipped(result.substitute(subst)),
+        }
+    }
+
+    /// Whether this proof is complete, i.e. does not contain any unproven goal
+    pub fn is_complete(&self) -> bool {
+        match self {
+            ProofStep::Apply { apply_on, .. } => apply_on.iter().all(|step| step.is_complete()),
+            ProofStep::Hyp { .. } => true,
+            ProofStep::Skipped { .. } => false,
+        }
+    }
+
+    /// The goals left unproven in this proof, in order
+    pub fn skipped_goals(&self) -> Vec<Formula> {
+        match self {
+            ProofStep::Apply { apply_on, .. } => apply_on.iter().flat_map(ProofStep::skipped_goals).collect(),
+            ProofStep::Hyp { .. } => vec![],
+            ProofStep::Skipped { result } => vec![result.clone()],
+        }
+    }
+
+    /// Collect the variables of all the formulas of this proof, in order of first appearance,
+    /// and the pairs of variables which shall be distinct because of the disjoint variable conditions of the theorems applied
+    pub fn collect_variables(&self, db: &Db, variables: &mut Vec<String>, disjoint: &mut BTreeSet<(String, String)>) {
+        for variable in db.variable_names(self.result()) {
+            if !variables.contains(&variable) {
+                variables.push(variable);
+            }
+        }
+        if let ProofStep::Apply { apply, apply_on, substitutions, .. } = self {
+            for (first, second) in db.disjoint_floating_hypotheses(*apply) {
+                if let (Some(first), Some(second)) = (substitutions.get(first), substitutions.get(second)) {
+                    for x in db.variable_names(first) {
+                        for y in db.variable_names(second) {
+                            match x.cmp(&y) {
+                                Ordering::Less => { disjoint.insert((x.clone(), y)); },
+                                Ordering::Greater => { disjoint.insert((y, x.clone())); },
+                                Ordering::Equal => {},
+                            }
+                        }
+                    }
+                }
+            }
+            for step in apply_on.iter() {
+                step.collect_variables(db, variables, disjoint);
+            }
         }
     }
 
@@ -57,14 +137,13 @@ impl ProofStep {
         stack_buffer: &mut Vec<u8>,
         arr: &mut ProofTreeArray,
         db: Db,
-    ) -> Option<usize> {
+    ) -> TacticsResult<usize> {
     	match self {
     		ProofStep::Apply { apply, apply_on, result, substitutions } => {
 		        let hyps = apply_on
 		            .iter()
 		            .map(|step| step.add_to_proof_tree_array(stack_buffer, arr, db.clone()))
-		            .flatten()
-		            .collect();
+		            .collect::<TacticsResult<Vec<usize>>>()?;
 		        db.build_proof_step(
 		            *apply,
 		            result.clone(),
@@ -80,57 +159,189 @@ impl ProofStep {
                     result.clone(),
                     stack_buffer,
                     arr,
-                )
+                ).ok_or(TacticsError::UnknownLabel(*label, None))
     		},
+    		ProofStep::Skipped { .. } => Err(TacticsError::Skipped),
     	}
     }
 
-    pub fn as_proof_tree_array(&self, db: Db) -> ProofTreeArray {
+    pub fn as_proof_tree_array(&self, db: Db) -> TacticsResult<ProofTreeArray> {
         let mut arr = ProofTreeArray::default();
         let mut stack_buffer = vec![];
-        arr.qed = self
-            .add_to_proof_tree_array(&mut stack_buffer, &mut arr, db)
-            .unwrap();
-        arr
+        arr.qed = self.add_to_proof_tree_array(&mut stack_buffer, &mut arr, db)?;
+        Ok(arr)
+    }
+
+    /// Export this proof in the mmp format.
+    /// Unlike `Db::export_mmp`, this does not require the proof to be complete: unproven goals appear as `?` steps.
+    pub fn export_incomplete_mmp<W: Write + ?Sized>(&self, theorem: Label, db: &Db, out: &mut W) -> std::io::Result<()> {
+        let mut steps = vec![];
+        self.add_mmp_steps(db, &mut steps);
+        writeln!(out, "$( <MM> <PROOF_ASST> THEOREM={}  LOC_AFTER=?\n", DisplayPair(&theorem, db))?;
+        let last = steps.len();
+        for (number, (prefix, hyps, reference, result)) in steps.iter().enumerate() {
+            let name = if number + 1 == last && prefix.is_empty() { "qed".to_string() } else { format!("{}{}", prefix, number + 1) };
+            writeln!(out, "{}:{}:{} {}", name, hyps, reference, result)?;
+        }
+        writeln!(out, "\n$)")
+    }
+
+    /// Add the mmp steps for this proof, returning the step number
+    fn add_mmp_steps(&self, db: &Db, steps: &mut Vec<(&'static str, String, String, String)>) -> usize {
+        let step = match self {
+            ProofStep::Apply { apply, apply_on, result, .. } => {
+                let hyps: Vec<String> = apply_on.iter().map(|step| step.add_mmp_steps(db, steps).to_string()).collect();
+                ("", hyps.join(","), apply.to_string(db), result.to_string(db))
+            },
+            ProofStep::Hyp { label, result } => ("h", String::new(), label.to_string(db), result.to_string(db)),
+            ProofStep::Skipped { result } => ("", "?".to_string(), String::new(), result.to_string(db)),
+        };
+        steps.push(step);
+        steps.len()
+    }
+
+    /// Check that each step is a correct application of its theorem,
+    /// and return an error describing the first incorrect step found.
+    pub fn check(&self, db: &Db) -> TacticsResult<()> {
+        if let ProofStep::Apply { apply, apply_on, result, substitutions } = self {
+            for step in apply_on.iter() {
+                step.check(db)?;
+            }
+            let (formula, hyps) = db.get_theorem_formulas(*apply).ok_or(TacticsError::UnknownLabel(*apply, None))?;
+            for variable in db.floating_hypotheses(*apply).ok_or(TacticsError::UnknownLabel(*apply, None))? {
+                if substitutions.get(variable).is_none() {
+                    return Err(TacticsError::MissingSubstitution(*apply, variable));
+                }
+            }
+            if hyps.len() != apply_on.len() {
+                return Err(TacticsError::InvalidStep(*apply, format!("{} hypotheses expected, {} provided", hyps.len(), apply_on.len())));
+            }
+            let expected = formula.substitute(substitutions);
+            if expected != *result {
+                return Err(TacticsError::InvalidStep(*apply, format!("expected {}, found {}", DisplayPair(&expected, db), DisplayPair(result, db))));
+            }
+            for ((hyp_label, hyp_formula), step) in hyps.iter().zip(apply_on.iter()) {
+                let expected = hyp_formula.substitute(substitutions);
+                if expected != *step.result() {
+                    return Err(TacticsError::InvalidStep(*apply, format!("hypothesis {} expected {}, found {}", DisplayPair(hyp_label, db), DisplayPair(&expected, db), DisplayPair(step.result(), db))));
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 pub struct ProofDefinition {
-    theorem: Label,
+    /// The theorem of the database to prove, if it is not a new theorem
+    theorem: Option<Label>,
+    /// The statement of the theorem, when it is not taken from the database but given in the script
+    new_theorem: Option<NewTheorem>,
+    /// Whether the new theorem is used by the following proofs once proven
+    lemma: bool,
+    location: Location,
     tactics: TacticsExpression,
 }
 
 impl Display for ProofDefinition {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("proof ")?;
-        self.theorem.format(fmt, db)?;
-        self.tactics.format(fmt, db)
+        match &self.new_theorem {
+            Some(theorem) => {
+                fmt.write_fmt(format_args!("{} {} (", if self.lemma { "lemma" } else { "proof" }, theorem.name))?;
+                for hypothesis in &theorem.hypotheses {
+                    fmt.write_fmt(format_args!(" $ {} $", hypothesis))?;
+                }
+                fmt.write_fmt(format_args!(" ) $ {} $ ", theorem.conclusion))?;
+            },
+            None => {
+                fmt.write_str("proof ~")?;
+                if let Some(theorem) = self.theorem {
+                    theorem.format(fmt, db)?;
+                }
+                fmt.write_str(" ")?;
+            },
+        }
+        self.tactics.format(fmt, db)?;
+        fmt.write_str("\n")
     }
 }
 
 impl Parse for ProofDefinition {
     fn parse(parser: &mut Parser) -> Result<Self> {
-        let theorem = parser.parse_theorem_label()?;
+        let expected = "A theorem label, or the name of a new theorem";
+        let token = parser.parse_mandatory_token(expected)?;
+        let location = parser.location();
+        let (theorem, new_theorem) = match token {
+            Token::TheoremLabel(name) => (Some(parser.get_theorem_label(name)?), None),
+            Token::Identifier(name) => (None, Some(parser.parse_new_theorem(name)?)),
+            token => return Err(parser.parse_error(expected, token)),
+        };
         let tactics = parser.parse_tactics()?;
-        Ok(ProofDefinition { theorem, tactics })
+        Ok(ProofDefinition { theorem, new_theorem, lemma: false, location, tactics })
     }
 }
 
 impl ProofDefinition {
-    pub fn prove(&self, db: Db, tactics_definitions: TacticsDict) -> std::result::Result<ProofStep, TacticsError> {
-        if let Some((theorem_formula, essential_hypotheses)) = db.get_theorem_formulas(self.theorem) {
-            println!("====================================================\n\n");
-            println!("Proof for {:?}:", self.theorem.to_string(&db));
+    /// Parse a lemma, after its `lemma` keyword: a new theorem, which the following proofs may use once it is proven
+    pub fn parse_lemma(parser: &mut Parser) -> Result<Self> {
+        let expected = "The name of the new lemma";
+        let token = parser.parse_mandatory_token(expected)?;
+        let location = parser.location();
+        let name = match token {
+            Token::Identifier(name) => name,
+            token => return Err(parser.parse_error(expected, token)),
+        };
+        let new_theorem = parser.parse_new_theorem(name)?;
+        let tactics = parser.parse_tactics()?;
+        Ok(ProofDefinition { theorem: None, new_theorem: Some(new_theorem), lemma: true, location, tactics })
+    }
+
+    /// Prove the theorem, given its label, returning the proof found along with the messages of the proof search.
+    pub fn prove(&self, theorem: Label, db: Db, tactics_definitions: TacticsDict, settings: &Settings) -> (std::result::Result<ProofStep, TacticsError>, String) {
+        let mut log = String::new();
+        if !settings.trace.json {
+            log.push_str("====================================================\n\n\n");
+        }
+        if let Some((theorem_formula, essential_hypotheses)) = db.get_theorem_formulas(theorem) {
             let mut context =
-                Context::new(db.clone(), theorem_formula, essential_hypotheses, tactics_definitions);
-            self.tactics.execute(&mut context)
+                Context::new(db.clone(), Some(theorem), theorem_formula, essential_hypotheses, tactics_definitions, settings);
+            context.trace(Level::Info, &format!("Proof for {:?}:", theorem.to_string(&db)));
+            // Replace the work variables by the formulas they have been bound to
+            let result = self.tactics.execute(&mut context)
+                .map(|step| step.substitute(context.work_variables().bindings()));
+            log.push_str(&context.log());
+            (result, log)
         } else {
-            println!("Unknown theorem {:?}!", self.theorem);
-            Err(TacticsError::UnknownLabel(self.theorem))
+            log.push_str(&settings.trace.format(Level::Warn, "proof", 0, &format!("Unknown theorem {}!", theorem.to_string(&db))));
+            (Err(TacticsError::UnknownLabel(theorem, Some(self.location.clone()))), log)
         }
     }
 
-    pub fn theorem(&self) -> Label {
-        self.theorem
+    /// Check the tactics of the proof, where no variable is in scope
+    pub fn check(&self, tactics_definitions: &TacticsDict) -> Vec<Error> {
+        let mut scope = CheckScope::new(tactics_definitions, &[]);
+        self.tactics.check(&mut scope);
+        scope.into_errors()
+    }
+
+    /// The label of the theorem to prove.
+    /// New theorems only have one once added to the database, when the script is executed.
+    pub fn theorem(&self, db: &Db) -> Option<Label> {
+        match &self.new_theorem {
+            Some(theorem) => db.get_theorem_label(theorem.name.clone()),
+            None => self.theorem,
+        }
+    }
+
+    pub fn new_theorem(&self) -> Option<&NewTheorem> {
+        self.new_theorem.as_ref()
+    }
+
+    /// Where the proof is given in the script
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    pub fn is_lemma(&self) -> bool {
+        self.lemma
     }
 }
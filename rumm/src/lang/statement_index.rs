@@ -0,0 +1,109 @@
+use crate::lang::Hypotheses;
+use metamath_rs::Formula;
+use metamath_rs::Label;
+use std::collections::{HashMap, HashSet};
+
+/// An axiom or a provable statement, as stored in the statement index.
+pub struct IndexedStatement {
+    pub label: Label,
+    pub name: Box<[u8]>,
+    pub is_axiom: bool,
+    /// Whether the statement is marked "(New usage is discouraged.)"
+    pub usage_discouraged: bool,
+    pub formula: Formula,
+    pub hyps: Hypotheses,
+}
+
+/// An index of the axioms and provable statements of the database, built once the database is loaded.
+/// Statements are bucketed by the root symbol of their conclusion, so that searches only consider
+/// the statements which can possibly match a given goal.
+#[derive(Default)]
+pub struct StatementIndex {
+    /// All statements, in database order
+    statements: Vec<IndexedStatement>,
+    /// The positions of the statements, by label
+    positions: HashMap<Label, usize>,
+    /// The positions of the statements whose conclusion has the given root symbol
+    by_root: HashMap<Label, Vec<usize>>,
+    /// The positions of the statements whose conclusion is a single variable, which match any goal
+    variable_root: Vec<usize>,
+    /// The labels of the floating hypotheses, i.e. of the variables
+    variables: HashSet<Label>,
+}
+
+impl StatementIndex {
+    pub fn new(statements: Vec<IndexedStatement>, variables: HashSet<Label>) -> Self {
+        let mut positions = HashMap::new();
+        let mut by_root: HashMap<Label, Vec<usize>> = HashMap::new();
+        let mut variable_root = vec![];
+        for (position, statement) in statements.iter().enumerate() {
+            positions.insert(statement.label, position);
+            match statement.formula.get_by_path(&[]) {
+                Some(root) if !variables.contains(&root) => by_root.entry(root).or_default().push(position),
+                _ => variable_root.push(position),
+            }
+        }
+        StatementIndex { statements, positions, by_root, variable_root, variables }
+    }
+
+    /// All statements, in database order, only up to the given position if any
+    pub fn statements(&self, before: Option<usize>) -> impl Iterator<Item = &IndexedStatement> {
+        let end = before.map_or(self.statements.len(), |before| before.min(self.statements.len()));
+        self.statements[..end].iter()
+    }
+
+    /// The statements whose conclusion may match the given target, in database order, only up to the given position if any
+    pub fn candidates(&self, target: &Formula, before: Option<usize>) -> Vec<&IndexedStatement> {
+        let root = match target.get_by_path(&[]) {
+            Some(root) if !self.variables.contains(&root) => root,
+            // A variable target may match any statement
+            _ => return self.statements(before).collect(),
+        };
+        let end = before.unwrap_or(usize::MAX);
+        let bucket = self.by_root.get(&root).map(Vec::as_slice).unwrap_or_default();
+        // Merge both buckets, keeping the database order
+        let (mut i, mut j) = (0, 0);
+        let mut candidates = Vec::with_capacity(bucket.len() + self.variable_root.len());
+        loop {
+            let next = match (bucket.get(i), self.variable_root.get(j)) {
+                (Some(&p), Some(&q)) if p < q => { i += 1; p },
+                (_, Some(&q)) => { j += 1; q },
+                (Some(&p), None) => { i += 1; p },
+                (None, None) => break,
+            };
+            if next >= end { break; }
+            candidates.push(&self.statements[next]);
+        }
+        candidates
+    }
+
+    /// The position of the given statement in the database order
+    pub fn position(&self, label: Label) -> Option<usize> {
+        self.positions.get(&label).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang::Db;
+    use crate::test_support::{load_db, DATABASE};
+
+    /// The names of the candidate statements for the conclusion of the given statement
+    fn candidates(db: &Db, name: &str, before: Option<usize>) -> Vec<String> {
+        let index = db.statement_index();
+        let label = db.get_theorem_label(name.to_string()).unwrap();
+        let target = &index.statements(None).find(|s| s.label == label).unwrap().formula;
+        index.candidates(target, before).iter().map(|s| String::from_utf8_lossy(&s.name).to_string()).collect()
+    }
+
+    #[test]
+    fn candidates_by_root() {
+        let (db, _file) = load_db(DATABASE);
+        // Statements concluding with a single variable match any goal, and database order is kept
+        assert_eq!(candidates(&db, "ax-1", None), ["ax-1", "ax-mp", "id", "bt"]);
+        assert_eq!(candidates(&db, "ax-dn", None), ["ax-dn", "ax-mp", "id", "bt"]);
+        assert_eq!(candidates(&db, "ax-mp", None), ["ax-1", "ax-dn", "ax-mp", "id", "bt"]);
+        assert_eq!(candidates(&db, "ax-dn", Some(3)), ["ax-dn", "ax-mp"]);
+        assert_eq!(db.statement_index().position(db.get_theorem_label("ax-mp".to_string()).unwrap()), Some(2));
+    }
+}
@@ -1,12 +1,13 @@
 use crate::lang::Expression;
 use crate::tactics::TacticsError;
 use crate::lang::TacticsExpression;
+use crate::check::CheckScope;
 use crate::context::Context;
-use crate::error::{Result, Error};
+use crate::error::{Location, Result, Error};
 use crate::lang::ParameterDefinition;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser, Token};
-use crate::tactics::TacticsResult;
+use crate::tactics::{Continuation, TacticsResult};
 use core::fmt::Formatter;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -40,30 +41,44 @@ impl TacticsDict {
     pub fn get(&self, name: String) -> Option<&TacticsDefinition> {
         self.0.get(&name)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TacticsDefinition> {
+        self.0.values()
+    }
 }
 
 pub struct TacticsDefinition {
     pub name: String,
     pub description: String,
     pub parameter_definition: Vec<ParameterDefinition>,
+    /// Where the tactics is defined in the script
+    pub location: Location,
     tactics: TacticsExpression,
 }
 
 impl Display for TacticsDefinition {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("/**")?;
-        fmt.write_str(&self.description)?;
-        fmt.write_str("*/")?;
+        // The description is kept with its comment delimiters, when one was given
+        if self.description.starts_with("/**") {
+            fmt.write_str(&self.description)?;
+            fmt.write_str("\n")?;
+        }
         fmt.write_str("tactics ")?;
         fmt.write_str(&self.name)?;
+        fmt.write_str(" (")?;
+        for p in &self.parameter_definition {
+            fmt.write_fmt(format_args!(" {}", p))?;
+        }
+        fmt.write_str(" ) ")?;
         self.tactics.format(fmt, db)?;
-        Ok(())
+        fmt.write_str("\n")
     }
 }
 
 impl Parse for TacticsDefinition {
     fn parse(parser: &mut Parser) -> Result<Self> {
         let name = parser.parse_identifier()?;
+        let location = parser.location();
         let description = parser
             .last_description()
             .unwrap_or_else(|| "* no description provided *".to_string());
@@ -78,6 +93,7 @@ impl Parse for TacticsDefinition {
             name,
             description,
             parameter_definition,
+            location,
             tactics,
         })
     }
@@ -97,7 +113,21 @@ impl TacticsDefinition {
         self.tactics.execute(context)
     }
 
-    pub fn add_variables(&self, context: &mut Context, parameters: &Vec<Expression>) -> TacticsResult<()> {
+    pub fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+        self.tactics.execute_then(context, then)
+    }
+
+    /// Check the tactics, with its parameters in scope
+    pub fn check(&self, tactics_definitions: &TacticsDict) -> Vec<Error> {
+        let mut scope = CheckScope::new(tactics_definitions, &self.parameter_definition);
+        self.tactics.check(&mut scope);
+        scope.into_errors()
+    }
+
+    pub fn add_variables(&self, context: &mut Context, parameters: &Vec<Expression>, location: &Location) -> TacticsResult<()> {
+        if parameters.len() != self.parameter_definition.len() {
+            return Err(TacticsError::WrongParameterCount(self.parameter_definition.len(), parameters.len(), location.clone()));
+        }
         for (param, def) in parameters.iter().zip(self.parameter_definition.iter()) {
             // Set parameters as variables in the context...
             match (param, def) {
@@ -105,7 +135,7 @@ impl TacticsDefinition {
                 (Expression::Statement(l), ParameterDefinition::Theorem(id)) => { context.add_label_variable(id.to_string(), l.evaluate(context)?); },
                 (Expression::Formula(f), ParameterDefinition::Formula(id)) => { context.add_formula_variable(id.to_string(), f.evaluate(context)?); },
                 (Expression::SubstitutionList(l), ParameterDefinition::SubstitutionList(id)) => { context.add_substitution_variable(id.to_string(), l.evaluate(context)?); },
-                _ => Err(TacticsError::WrongParameterCount(self.parameter_definition.len(), parameters.len()))?
+                _ => Err(TacticsError::WrongParameterCount(self.parameter_definition.len(), parameters.len(), location.clone()))?
             }
         }
         Ok(())
@@ -0,0 +1,39 @@
+//! A Metamath Proof Assistant
+//!
+//! Rumm proves Metamath theorems with tactics. Besides the `rumm` command line tool, it can be embedded:
+//! load a database with [`Db::load`], parse a script with [`Script::from_str_with_db`],
+//! or single tactics and formulas with [`Script::parse_tactics`] and [`Script::parse_formula`],
+//! then run tactics on a goal with [`Script::prove`], which returns the [`ProofStep`] found,
+//! or a [`TacticsError`] describing why the tactics failed.
+//! Library calls do not print anything: [`Script::execute`] writes its results and traces where it is told to,
+//! and the progress of database loading is reported through the `log` crate.
+
+mod cache;
+mod check;
+pub mod context;
+pub mod error;
+pub mod lang;
+pub mod parser;
+pub mod script;
+pub mod summary;
+pub mod tactics;
+#[cfg(test)]
+mod test_support;
+pub mod trace;
+
+// The modules below are only used by the `rumm` command line tool, and are only built with the `cli` feature.
+#[cfg(feature = "cli")]
+pub mod format;
+#[cfg(feature = "cli")]
+pub mod lsp;
+#[cfg(feature = "cli")]
+pub mod repl;
+
+pub use cache::ProofCache;
+pub use context::{Context, Settings};
+pub use error::{Error, Result};
+pub use lang::{Db, Hypotheses, ProofStep, TacticsExpression};
+pub use script::{ProofOutput, Script};
+pub use summary::{Outcome, ProofOutcome};
+pub use tactics::{Tactics, TacticsError, TacticsRegistry, TacticsResult};
+pub use trace::TraceSettings;
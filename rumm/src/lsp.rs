@@ -0,0 +1,460 @@
+//! A language server for Rumm files, talking the Language Server Protocol on the standard input and output
+use crate::error::{Error, Location, Result};
+use crate::lang::{Db, DisplayPair};
+use crate::parser::Token;
+use crate::script::Script;
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use logos::Logos;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionList, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use metamath_rs::as_str;
+use crate::tactics::TacticsRegistry;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+type LspResult<T = ()> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A database loaded in the background, by path, or the reason it could not be loaded
+type Loaded = (String, std::result::Result<Db, String>);
+
+/// The maximum number of completions returned at once
+const MAX_COMPLETIONS: usize = 200;
+
+/// The characters which can be part of a label, of an identifier or of a variable of a Rumm file
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "~≈@+*.-_".contains(c)
+}
+
+/// An open Rumm file
+struct Document {
+    text: String,
+    version: i32,
+    /// The last script successfully parsed from this file, if any
+    script: Option<Script>,
+}
+
+impl Document {
+    fn line(&self, position: Position) -> &str {
+        self.text.lines().nth(position.line as usize).unwrap_or("")
+    }
+
+    /// The word under the given position, along with the part of it before the position
+    fn word_at(&self, position: Position) -> (&str, &str) {
+        let line = self.line(position);
+        let offset = offset(line, position.character);
+        let start = line[..offset].rfind(|c| !is_word_char(c)).map_or(0, |i| i + line[i..].chars().next().unwrap().len_utf8());
+        let end = line[offset..].find(|c| !is_word_char(c)).map_or(line.len(), |i| offset + i);
+        (&line[start..end], &line[start..offset])
+    }
+
+    /// The math token before the given position, if the position is within a formula `$ ... $`
+    fn math_prefix(&self, position: Position) -> Option<&str> {
+        let before_lines: usize = self.text.lines().take(position.line as usize).map(|line| line.len() + 1).sum();
+        let line = self.line(position);
+        let offset = offset(line, position.character);
+        let before = self.text.get(..before_lines + offset)?;
+        // Dollar signs within comments and strings do not delimit formulas
+        if Token::lexer(before).filter(|token| *token == Token::FormulaStart).count() % 2 == 0 {
+            return None;
+        }
+        let start = line[..offset].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        Some(&line[start..offset])
+    }
+
+    /// The path of the database loaded by the `load` command of this file, relative to the file, and the range of the command
+    fn database(&self, filename: &str) -> Option<(String, Range)> {
+        let mut lexer = Token::lexer(&self.text);
+        while let Some(token) = lexer.next() {
+            if token == Token::LoadKeyword {
+                let start = lexer.span().start;
+                let database = match lexer.next() {
+                    Some(Token::StringConstant(database)) => database,
+                    _ => return None,
+                };
+                let path = Path::new(filename).parent().map_or_else(|| Path::new(&database).to_path_buf(), |directory| directory.join(&database));
+                let range = Range::new(position(&self.text, start), position(&self.text, lexer.span().end));
+                return Some((path.to_string_lossy().to_string(), range));
+            }
+        }
+        None
+    }
+}
+
+/// The file name of a document
+fn filename(uri: &Url) -> String {
+    uri.to_file_path().map_or_else(|_| uri.path().to_string(), |path| path.to_string_lossy().to_string())
+}
+
+/// The byte offset in the line of the given UTF-16 based character position
+fn offset(line: &str, character: u32) -> usize {
+    let mut utf16 = 0;
+    for (index, c) in line.char_indices() {
+        if utf16 >= character as usize {
+            return index;
+        }
+        utf16 += c.len_utf16();
+    }
+    line.len()
+}
+
+/// The UTF-16 based character position of the given byte offset in the line
+fn column(line: &str, offset: usize) -> u32 {
+    line.get(..offset.min(line.len())).map_or(0, |before| before.encode_utf16().count() as u32)
+}
+
+/// The position of the given byte offset in the text
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(before.matches('\n').count() as u32, column(&text[line_start..], offset - line_start))
+}
+
+fn range(location: &Location) -> Range {
+    let line = location.line_number.saturating_sub(1) as u32;
+    Range::new(
+        Position::new(line, column(&location.line, location.start)),
+        Position::new(line, column(&location.line, location.end)),
+    )
+}
+
+/// The message of an error, followed by the errors causing it
+fn message(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut cause = error.caused_by();
+    while let Some(error) = cause {
+        message.push_str(&format!("\n{}", error));
+        cause = error.caused_by();
+    }
+    message
+}
+
+/// A database loaded by the scripts
+enum Database {
+    /// Being loaded in the background, the documents using it are checked once it is loaded
+    Loading,
+    Loaded(Db),
+}
+
+struct Server {
+    connection: Connection,
+    /// The databases loaded by the scripts, by path, kept across parses
+    databases: HashMap<String, Database>,
+    /// Where the databases loaded in the background are sent
+    loaded: Sender<Loaded>,
+    /// The tactics which may be used by the scripts
+    registry: Arc<TacticsRegistry>,
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn main_loop(&mut self, loaded: Receiver<Loaded>) -> LspResult {
+        let receiver = self.connection.receiver.clone();
+        loop {
+            select! {
+                recv(receiver) -> message => match message {
+                    Ok(Message::Request(request)) => {
+                        if self.connection.handle_shutdown(&request)? {
+                            return Ok(());
+                        }
+                        self.handle_request(request)?;
+                    },
+                    Ok(Message::Notification(notification)) => self.handle_notification(notification)?,
+                    Ok(Message::Response(_)) => {},
+                    // The client closed the connection
+                    Err(_) => return Ok(()),
+                },
+                recv(loaded) -> database => {
+                    let (path, db) = database?;
+                    self.loaded(path, db)?;
+                },
+            }
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) -> LspResult {
+        let Request { id, method, params } = request;
+        let result = match method.as_str() {
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(params)?;
+                serde_json::to_value(self.completion(params.text_document_position))?
+            },
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(params)?;
+                serde_json::to_value(self.hover(params.text_document_position_params))?
+            },
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(params)?;
+                serde_json::to_value(self.definition(params.text_document_position_params))?
+            },
+            _ => serde_json::Value::Null,
+        };
+        self.respond(id, result)
+    }
+
+    fn respond(&self, id: RequestId, result: serde_json::Value) -> LspResult {
+        self.connection.sender.send(Message::Response(Response::new_ok(id, result)))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> LspResult {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.update(params.text_document.uri, params.text_document.text, params.text_document.version)?;
+            },
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                // Documents are synchronized in full, the last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text, params.text_document.version)?;
+                }
+            },
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    /// Keep the new text of a document which changed, and check it again
+    fn update(&mut self, uri: Url, text: String, version: i32) -> LspResult {
+        let document = self.documents.entry(uri.clone()).or_insert(Document { text: String::new(), version, script: None });
+        document.text = text;
+        document.version = version;
+        self.check(&uri)
+    }
+
+    /// Parse and check again a document, and publish the problems found.
+    /// The database it loads is loaded in the background the first time, the document is checked once it is loaded.
+    fn check(&mut self, uri: &Url) -> LspResult {
+        let filename = filename(uri);
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Ok(()),
+        };
+        let db = match document.database(&filename) {
+            Some((path, _)) => match self.databases.get(&path) {
+                Some(Database::Loaded(db)) => db.clone(),
+                Some(Database::Loading) => return Ok(()),
+                None => {
+                    let (loaded, database) = (self.loaded.clone(), path.clone());
+                    std::thread::spawn(move || {
+                        let db = Db::load(&database).map_err(|error| message(&error));
+                        // The server may have stopped meanwhile
+                        let _ = loaded.send((database, db));
+                    });
+                    self.databases.insert(path, Database::Loading);
+                    return Ok(());
+                },
+            },
+            None => Db::default(),
+        };
+        let (script, errors) = match Script::from_str_with_db(filename.clone(), &document.text, db, self.registry.clone()) {
+            Ok(script) => {
+                let errors = script.check();
+                (Some(script), errors)
+            },
+            Err(error) => (None, vec![error]),
+        };
+        let diagnostics = errors.iter()
+            // Problems within included files are reported where the file is included
+            .filter(|error| error.location().map_or(true, |location| location.filename == filename))
+            .map(|error| Diagnostic {
+                range: error.location().map(range).unwrap_or_default(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("rumm".to_string()),
+                message: message(error),
+                ..Diagnostic::default()
+            })
+            .collect();
+        let version = document.version;
+        // Keep the last script parsed, for completion and navigation while the file is being edited
+        if let (Some(script), Some(document)) = (script, self.documents.get_mut(uri)) {
+            document.script = Some(script);
+        }
+        self.publish(uri.clone(), diagnostics, version)
+    }
+
+    /// Check the documents using a database loaded in the background, or report why it could not be loaded
+    fn loaded(&mut self, path: String, db: std::result::Result<Db, String>) -> LspResult {
+        let documents: Vec<(Url, Range, i32)> = self.documents.iter()
+            .filter_map(|(uri, document)| match document.database(&filename(uri)) {
+                Some((database, range)) if database == path => Some((uri.clone(), range, document.version)),
+                _ => None,
+            })
+            .collect();
+        match db {
+            Ok(db) => {
+                self.databases.insert(path, Database::Loaded(db));
+                for (uri, _, _) in documents {
+                    self.check(&uri)?;
+                }
+            },
+            Err(error) => {
+                // The database is loaded again at the next change of a document using it
+                self.databases.remove(&path);
+                for (uri, range, version) in documents {
+                    let diagnostic = Diagnostic {
+                        range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        source: Some("rumm".to_string()),
+                        message: error.clone(),
+                        ..Diagnostic::default()
+                    };
+                    self.publish(uri, vec![diagnostic], version)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>, version: i32) -> LspResult {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, Some(version));
+        self.connection.sender.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))?;
+        Ok(())
+    }
+
+    /// Complete theorem labels, math symbols within formulas, and tactics names, registered or defined by the script
+    fn completion(&self, position: TextDocumentPositionParams) -> Option<CompletionResponse> {
+        let document = self.documents.get(&position.text_document.uri)?;
+        let mut items = vec![];
+        let (_, prefix) = document.word_at(position.position);
+        // The database is the one of the last script parsed, there is none until the database is loaded
+        let db = document.script.as_ref().map(Script::db);
+        if let Some(math_prefix) = document.math_prefix(position.position) {
+            let mut symbols = db.map(Db::math_symbols).unwrap_or_default();
+            symbols.sort();
+            symbols.dedup();
+            items.extend(symbols.into_iter()
+                .filter(|symbol| symbol.starts_with(math_prefix))
+                .map(|symbol| CompletionItem { label: symbol, kind: Some(CompletionItemKind::CONSTANT), ..CompletionItem::default() }));
+        } else if let Some(label_prefix) = prefix.strip_prefix('~') {
+            let index = db.map(Db::statement_index).unwrap_or_default();
+            items.extend(index.statements(None)
+                .filter(|statement| as_str(&statement.name).starts_with(label_prefix))
+                .map(|statement| CompletionItem {
+                    label: format!("~{}", as_str(&statement.name)),
+                    kind: Some(if statement.is_axiom { CompletionItemKind::CONSTANT } else { CompletionItemKind::FUNCTION }),
+                    detail: db.map(|db| DisplayPair(&statement.formula, db).to_string()),
+                    ..CompletionItem::default()
+                }));
+        } else {
+            let mut names: Vec<&str> = self.registry.names().filter(|name| name.starts_with(prefix)).collect();
+            names.sort_unstable();
+            items.extend(names.into_iter()
+                .map(|name| CompletionItem { label: name.to_string(), kind: Some(CompletionItemKind::KEYWORD), ..CompletionItem::default() }));
+            if let Some(script) = &document.script {
+                items.extend(script.tactics_definitions.iter()
+                    .filter(|definition| definition.name.starts_with(prefix))
+                    .map(|definition| CompletionItem {
+                        label: definition.name.clone(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        detail: Some(definition.description.clone()),
+                        ..CompletionItem::default()
+                    }));
+            }
+        }
+        let is_incomplete = items.len() > MAX_COMPLETIONS;
+        items.truncate(MAX_COMPLETIONS);
+        Some(CompletionResponse::List(CompletionList { is_incomplete, items }))
+    }
+
+    /// Show the statement of theorems, and the description of script tactics
+    fn hover(&self, position: TextDocumentPositionParams) -> Option<Hover> {
+        let document = self.documents.get(&position.text_document.uri)?;
+        let (word, _) = document.word_at(position.position);
+        let value = if let Some(name) = word.strip_prefix('~') {
+            let db = document.script.as_ref()?.db();
+            let label = db.get_theorem_label(name.to_string())?;
+            let (formula, hyps) = db.get_theorem_formulas(label)?;
+            let mut value = format!("**{}**\n\n```\n", name);
+            for (hyp_label, hyp) in hyps.iter() {
+                value.push_str(&format!("{} $e {}\n", DisplayPair(hyp_label, db), DisplayPair(hyp, db)));
+            }
+            value.push_str(&format!("{}\n```", DisplayPair(&formula, db)));
+            value
+        } else {
+            let definition = document.script.as_ref()?.tactics_definitions.get(word.to_string())?;
+            let parameters: Vec<_> = definition.parameter_definition.iter().map(|parameter| parameter.id()).collect();
+            format!("**tactics {}** ( {} )\n\n{}", definition.name, parameters.join(" "), definition.description)
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: None,
+        })
+    }
+
+    /// Jump to the definition of script tactics
+    fn definition(&self, position: TextDocumentPositionParams) -> Option<GotoDefinitionResponse> {
+        let document = self.documents.get(&position.text_document.uri)?;
+        let (word, _) = document.word_at(position.position);
+        let definition = document.script.as_ref()?.tactics_definitions.get(word.to_string())?;
+        let path = std::fs::canonicalize(&definition.location.filename).ok()?;
+        let uri = Url::from_file_path(path).ok()?;
+        Some(GotoDefinitionResponse::Scalar(lsp_types::Location::new(uri, range(&definition.location))))
+    }
+}
+
+/// Run the language server, until the client shuts it down.
+/// The scripts are parsed with the given tactics registry.
+pub fn run(registry: Arc<TacticsRegistry>) -> Result {
+    serve(registry).map_err(|error| Error::LspError(error.to_string()))
+}
+
+fn serve(registry: Arc<TacticsRegistry>) -> LspResult {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["~".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let (loaded, loaded_receiver) = unbounded();
+    let mut server = Server { connection, databases: HashMap::new(), loaded, registry, documents: HashMap::new() };
+    server.main_loop(loaded_receiver)?;
+    // The connection must be closed for the I/O threads to terminate
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, Position, Range};
+
+    fn document(text: &str) -> Document {
+        Document { text: text.to_string(), version: 0, script: None }
+    }
+
+    #[test]
+    fn math_prefix() {
+        let document = document("// Costs $5\nproof ~id { use \"$\" with +F $ ( ph -> $ }");
+        assert_eq!(document.math_prefix(Position::new(1, 31)), Some("("));
+        assert_eq!(document.math_prefix(Position::new(1, 20)), None);
+        assert_eq!(document.math_prefix(Position::new(0, 11)), None);
+    }
+
+    #[test]
+    fn database() {
+        let document = document("/* load \"other.mm\" */\nload \"set.mm\"");
+        let range = Range::new(Position::new(1, 0), Position::new(1, 13));
+        assert_eq!(document.database("/home/scripts/set.rmm"), Some(("/home/scripts/set.mm".to_string(), range)));
+        assert_eq!(document.database("set.rmm"), Some(("set.mm".to_string(), range)));
+    }
+}
@@ -1,34 +1,124 @@
-//! A Metamath Proof Assistant
+//! The command line interface of the Metamath Proof Assistant
 
-mod context;
-pub mod error;
-mod lang;
-pub mod parser;
-pub mod script;
-pub mod tactics;
 use annotate_snippets::display_list::DisplayList;
 use clap::{clap_app, crate_version};
-use error::Result;
-use script::Script;
+use log::LevelFilter;
+use rumm::{ProofCache, Settings};
+use rumm::error::{self, Result};
+use rumm::script::{ProofOutput, Script};
+use rumm::summary::{self, SummaryFormat};
+use rumm::tactics::TacticsRegistry;
+use rumm::trace::{self, TraceSettings};
+use rumm::{format, lsp, repl};
 use typed_arena::Arena;
 use std::fs;
+use std::sync::Arc;
 
 fn run() -> Result {
     let app = clap_app!(("rumm") =>
         (version: crate_version!())
         (about: "A tactics based proof language for Metamath")
-        (@arg RMM_FILE: "Rumm file to load"));
+        (@arg RMM_FILE: "Rumm file to load")
+        (@arg UPDATE: -u --update "Write the proofs found back into the loaded database file")
+        (@arg OUTPUT: -o --output +takes_value "Write the database with the proofs found into the given file, leaving the loaded one untouched")
+        (@arg BACKTRACK: -b --backtrack +takes_value "Enable backtracking, retrying earlier choices at most the given number of times per proof")
+        (@arg JOBS: -j --jobs +takes_value "Number of proofs searched in parallel, all available processors are used by default")
+        (@arg PARALLEL: -p --parallel "Evaluate the candidates of the find, finddf and try tactics in parallel")
+        (@arg UNORDERED: --unordered "Let searches use any statement of the database, including the ones following the theorem being proved")
+        (@arg ALLOW_DISCOURAGED: --("allow-discouraged") "Let searches use the statements whose new usage is discouraged")
+        (@arg AUTO_BUDGET: --("auto-budget") +takes_value "Maximum number of goals considered by each auto tactics, 10000 by default")
+        (@arg NO_CACHE: --("no-cache") "Do not reuse the results of script tactics already proven")
+        (@arg VERBOSE: -v --verbose ... "Trace the proof search, use twice for detailed traces")
+        (@arg QUIET: -q --quiet conflicts_with[VERBOSE] "Only print warnings and results")
+        (@arg TRACE_JSON: --("trace-json") "Write traces as JSON lines")
+        (@arg SUMMARY: --summary +takes_value "Write the outcome and duration of each proof into the given file, as JUnit XML for .xml files, and as JSON otherwise")
+        (@subcommand fmt =>
+            (about: "Format Rumm files in place, keeping their comments")
+            (@arg CHECK: --check "Only check that the files are formatted, fail otherwise")
+            (@arg RMM_FILES: +required ... "Rumm files to format"))
+        (@subcommand lsp =>
+            (about: "Run a language server for Rumm files, on the standard input and output"))
+        (@subcommand repl =>
+            (about: "Develop a proof interactively, one tactics at a time")
+            (@arg RMM_FILE: "Rumm file to load, for its database and tactics definitions")));
     let matches = app.get_matches();
-    let path = matches.value_of("RMM_FILE").unwrap_or("../set.rmm");
+    let level = match matches.occurrences_of("VERBOSE") {
+        _ if matches.is_present("QUIET") => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let trace = TraceSettings { level, json: matches.is_present("TRACE_JSON") };
+    trace::init(trace);
+    let registry = Arc::new(TacticsRegistry::default());
+    if let Some(fmt) = matches.subcommand_matches("fmt") {
+        let filenames: Vec<&str> = fmt.values_of("RMM_FILES").into_iter().flatten().collect();
+        return format::run(&filenames, fmt.is_present("CHECK"), &registry);
+    }
+    if matches.subcommand_matches("lsp").is_some() {
+        return lsp::run(registry);
+    }
+    let repl = matches.subcommand_matches("repl");
+    let path = repl.and_then(|repl| repl.value_of("RMM_FILE")).or_else(|| matches.value_of("RMM_FILE")).unwrap_or("../set.rmm");
+    let output = match matches.value_of("OUTPUT") {
+        Some(filename) => ProofOutput::Database(Some(filename.to_string())),
+        None if matches.is_present("UPDATE") => ProofOutput::Database(None),
+        None => ProofOutput::Mmp,
+    };
+    let backtrack_limit = match matches.value_of("BACKTRACK") {
+        Some(limit) => Some(limit.parse().map_err(|_| error::Error::InvalidArgument(format!("Invalid backtracking limit: {}", limit)))?),
+        None => None,
+    };
+    let auto_budget = match matches.value_of("AUTO_BUDGET") {
+        Some(budget) => Some(budget.parse().map_err(|_| error::Error::InvalidArgument(format!("Invalid auto budget: {}", budget)))?),
+        None => None,
+    };
     let data = fs::read_to_string(path)?; // TODO use map_err to map into an error storing the file name for context.
-    let mut script = Script::from_str(path.to_string(), &data)?;
-    script.execute()
+    let mut script = Script::from_str(path.to_string(), &data, registry)?;
+    let errors = script.check();
+    if !errors.is_empty() {
+        let arena: Arena<String> = Arena::new();
+        for error in &errors {
+            eprintln!("{}", DisplayList::from(error.to_snippet(&arena)));
+        }
+        return Err(error::Error::CheckFailed(errors.len()));
+    }
+    let jobs = match matches.value_of("JOBS") {
+        Some(jobs) => jobs.parse().map_err(|_| error::Error::InvalidArgument(format!("Invalid number of jobs: {}", jobs)))?,
+        None => 0,
+    };
+    let cache = if matches.is_present("NO_CACHE") { None } else { Some(ProofCache::default()) };
+    script.set_settings(Settings {
+        backtrack_limit,
+        cache,
+        jobs,
+        parallel_candidates: matches.is_present("PARALLEL"),
+        unordered: matches.is_present("UNORDERED"),
+        allow_discouraged: matches.is_present("ALLOW_DISCOURAGED"),
+        auto_budget,
+        trace,
+    });
+    if repl.is_some() {
+        return repl::run(&script);
+    }
+    let outcomes = script.execute(&output, &mut std::io::stdout(), &mut std::io::stderr())?;
+    if let Some(filename) = matches.value_of("SUMMARY") {
+        summary::write(&outcomes, filename, SummaryFormat::from_filename(filename))?;
+    }
+    let failures = outcomes.iter().filter(|proof| proof.outcome.is_failure()).count();
+    if failures > 0 {
+        return Err(error::Error::ProofsFailed(failures, outcomes.len()));
+    }
+    Ok(())
 }
 
 fn main() {
     let arena: Arena<String> = Arena::new();
     match run() {
-        Ok(()) => println!("Done."),
-        Err(error) => eprintln!("{}", DisplayList::from(error.to_snippet(&arena))),
+        Ok(()) => log::info!("Done."),
+        Err(error) => {
+            eprintln!("{}", DisplayList::from(error.to_snippet(&arena)));
+            std::process::exit(1);
+        },
     };
 }
@@ -10,7 +10,11 @@ use metamath_rs::Label;
 use metamath_rs::grammar::FormulaToken;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::collections::HashSet;
+use std::fs;
 use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // TODO in order to provide line number errors, I think we would need to implement Logos::Source...
 // OR we add a token for newlines, which we use to count the line, and the position of the last line start :)
@@ -26,6 +30,9 @@ pub enum Token {
     #[token("proof")]
     ProofKeyword,
 
+    #[token("lemma")]
+    LemmaKeyword,
+
     #[token("import")]
     ImportKeyword,
 
@@ -101,10 +108,13 @@ pub enum Token {
     // Descriptive comments
     DescriptiveComment(String),
 
+    #[regex(r"//[^\r\n]*", |lexer| String::from(lexer.slice()))] // Line comments
+    #[regex(r"/\*([^\*]|\*[^/])+\*/", |lexer| String::from(lexer.slice()))] // Multi-line comments
+    // Ordinary comments, kept by the parser with the following tactics or command
+    Comment(String),
+
     #[error]
     #[regex(r"[ \t\r\n]+", logos::skip)] // Skip spaces and line breaks
-    #[regex(r"//[^\r\n]*(\r\n|\n)?", logos::skip)] // Skip line comments
-    #[regex(r"/\*([^\*]|\*[^/])+\*/", logos::skip)] // Skip multi-line comments
     LexerError,
 }
 
@@ -145,8 +155,13 @@ pub struct Parser<'a> {
     filename: String,
     pub lexer: Lexer<'a, Token>,
     last_description: Option<String>,
+    /// The comments read since the last ones were taken
+    pending_comments: Vec<Comment>,
     db: Db,
-    debug: bool,
+    registry: Arc<TacticsRegistry>,
+    include_stack: Vec<PathBuf>,
+    /// The names of the theorems stated so far in the script, which are only added to the database when it is executed
+    new_theorems: HashSet<String>,
 }
 
 impl<'a> Parser<'a> {
@@ -156,27 +171,109 @@ impl<'a> Parser<'a> {
     // }
 
     pub fn from_str(filename: String, str: &'a str) -> Self {
+        Self::with_db(filename, str, Db::default())
+    }
+
+    /// Build a parser using an already loaded database, which is not loaded again by the `load` command.
+    pub fn with_db(filename: String, str: &'a str, db: Db) -> Self {
+        let include_stack = vec![Self::canonical_path(Path::new(&filename))];
         Parser {
             filename,
             lexer: Token::lexer(str),
             last_description: None,
-            db: Db::default(),
-            debug: true,
+            pending_comments: Vec::new(),
+            db,
+            registry: Arc::new(TacticsRegistry::default()),
+            include_stack,
+            new_theorems: HashSet::new(),
+        }
+    }
+
+    /// Use the given tactics registry, for example with tactics registered in addition to the built-in ones.
+    /// Included files are parsed with the same registry.
+    pub fn set_registry(&mut self, registry: Arc<TacticsRegistry>) {
+        self.registry = registry;
+    }
+
+    /// Build a parser using the given tactics registry, with a database to be loaded by the `load` command
+    pub fn with_registry(filename: String, str: &'a str, registry: Arc<TacticsRegistry>) -> Self {
+        Self::with_db_and_registry(filename, str, Db::default(), registry)
+    }
+
+    /// Build a parser using an already loaded database and the given tactics registry
+    pub fn with_db_and_registry(filename: String, str: &'a str, db: Db, registry: Arc<TacticsRegistry>) -> Self {
+        let mut parser = Self::with_db(filename, str, db);
+        parser.set_registry(registry);
+        parser
+    }
+
+    /// Build a parser for a file included from this one, sharing the same database.
+    fn included<'b>(&self, path: PathBuf, str: &'b str) -> Parser<'b> {
+        let mut include_stack = self.include_stack.clone();
+        include_stack.push(Self::canonical_path(&path));
+        Parser {
+            filename: path.to_string_lossy().to_string(),
+            lexer: Token::lexer(str),
+            last_description: None,
+            pending_comments: Vec::new(),
+            db: self.db.clone(),
+            registry: self.registry.clone(),
+            include_stack,
+            new_theorems: self.new_theorems.clone(),
+        }
+    }
+
+    fn canonical_path(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// Resolve a file name relative to the directory of the file being parsed.
+    fn resolve_path(&self, filename: &str) -> PathBuf {
+        match Path::new(&self.filename).parent() {
+            Some(directory) => directory.join(filename),
+            None => PathBuf::from(filename),
         }
     }
 
     pub fn next_token(&mut self) -> Option<Token> {
         let mut token = self.lexer.next();
-        while let Some(Token::DescriptiveComment(description)) = token {
-            self.last_description = Some(description);
+        loop {
+            match token {
+                Some(Token::DescriptiveComment(description)) => self.last_description = Some(description),
+                Some(Token::Comment(text)) => {
+                    let source = self.lexer.source();
+                    let start = self.lexer.span().start;
+                    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+                    let trailing = !source[line_start..start].trim().is_empty();
+                    self.pending_comments.push(Comment { text, trailing });
+                },
+                _ => break,
+            }
             token = self.lexer.next();
         }
-        if self.debug && false {
-            println!("\tToken: {:?} ({:?})", token, self.lexer.slice());
-        }
         token
     }
 
+    /// The comments read since the last ones were taken
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.pending_comments)
+    }
+
+    /// Whether the token starts a tactics, which then keeps the comments preceding it
+    fn starts_tactics(token: &Option<Token>) -> bool {
+        matches!(token, Some(Token::TacticsIdentifier(_)) | Some(Token::TodoKeyword) | Some(Token::HypoKeyword) | Some(Token::CurlyBracketOpen))
+    }
+
+    /// Keep with a tactics the comments preceding it, and the ones read up to its end
+    fn with_comments(&mut self, tactics: TacticsExpression, comments: Vec<Comment>) -> TacticsExpression {
+        let closing = self.take_comments();
+        if comments.is_empty() && closing.is_empty() {
+            tactics
+        } else {
+            TacticsExpression::Commented(Box::new(tactics), comments, closing)
+        }
+    }
+
     pub fn parse_mandatory_token(&mut self, expected: &str) -> Result<Token> {
         self.next_token().ok_or_else(|| self.unexpected_end_of_file(expected))        
     }
@@ -187,8 +284,11 @@ impl<'a> Parser<'a> {
         last_desscription
     }
 
+    /// The label of a theorem of the database, given its name starting with a tilde.
+    /// Theorems added when executing a previous script are not taken into account.
     pub fn get_theorem_label(&self, name: String) -> Result<Label> {
         self.db.get_theorem_label(name[1..].to_string())
+            .filter(|_| !self.db.is_added(&name[1..]))
             .ok_or(Error::UnknownLabel{
                 location: self.location(), 
                 label: name,
@@ -196,6 +296,16 @@ impl<'a> Parser<'a> {
         )
     }
 
+    /// The statement referred to by a theorem label, given its name starting with a tilde.
+    /// Theorems stated earlier in the script are only looked up when it is executed, once added to the database.
+    pub fn parse_statement_label(&self, name: String) -> Result<StatementExpression> {
+        if self.new_theorems.contains(&name[1..]) {
+            Ok(StatementExpression::New(name[1..].to_string(), self.location()))
+        } else {
+            Ok(StatementExpression::Constant(self.get_theorem_label(name)?, self.location()))
+        }
+    }
+
     fn get_symbol(&self, name: String) -> Result<Symbol> {
         self.db.get_symbol(name.clone())
             .ok_or(Error::UnknownSymbol{
@@ -238,6 +348,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a non-negative integer, like a search depth
+    pub fn parse_number(&mut self, expected: &str) -> Result<usize> {
+        match self.next_token() {
+            Some(Token::Identifier(text)) => text.parse().map_err(|_| Error::InvalidNumber { location: self.location(), expected: expected.to_string(), found: text }),
+            Some(token) => Err(self.parse_error(expected, token)),
+            None => Err(self.unexpected_end_of_file(expected)),
+        }
+    }
+
     pub fn parse_optional_statement(&mut self) -> Result<Option<Label>> {
         match self.next_token() {
             Some(Token::CurlyBracketClose) => Ok(None),
@@ -268,7 +387,7 @@ impl<'a> Parser<'a> {
         match self.next_token() {
             Some(Token::CurlyBracketClose) => Ok(None),
             Some(Token::TheoremLabel(name)) => Ok(Some(SubstitutionExpression::Constant((self.get_theorem_label(name)?, FormulaExpression::parse(self)?)))),
-            Some(Token::SubstitutionListIdentifier(id)) => Ok(Some(SubstitutionExpression::Variable(id))),
+            Some(Token::SubstitutionListIdentifier(id)) => Ok(Some(SubstitutionExpression::Variable(id, self.location()))),
             Some(token) => Err(self.parse_error("A Theorem Label", token)),
             None => Err(self.unexpected_end_of_file("A Theorem Label")),
         }
@@ -286,34 +405,41 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_optional_tactics(&mut self) -> Result<OptionalTactics> {
-        match self.next_token() {
-            Some(Token::CurlyBracketClose) => Ok(OptionalTactics::None),
-            Some(Token::TacticsIdentifier(id)) => Ok(OptionalTactics::Some(TacticsExpression::Variable(id))),
-            Some(Token::TodoKeyword) => TacticsExpression::cst(Skipped {}.into_arc()),
-            Some(Token::HypoKeyword) => TacticsExpression::cst(Hypothesis {}.into_arc()),
-            Some(Token::CurlyBracketOpen) => match self.next_token() {
-                Some(Token::Identifier(name)) => match name.as_ref() {
-                    "use" => TacticsExpression::cst(UseScriptTactics::parse(self)?.into_arc()),
-                    "subgoal" => TacticsExpression::cst(Subgoal::parse(self)?.into_arc()),
-                    "apply" => TacticsExpression::cst(Apply::parse(self)?.into_arc()),
-                    "try" => TacticsExpression::cst(Try::parse(self)?.into_arc()),
-                    "match" => TacticsExpression::cst(Match::parse(self)?.into_arc()),
-                    "find" => TacticsExpression::cst(Find::parse(self)?.into_arc()),
-                    "finddf" => TacticsExpression::cst(FindDf::parse(self)?.into_arc()),
-                    "findhyp" => TacticsExpression::cst(FindHyp::parse(self)?.into_arc()),
-                    _ => Err(Error::UnknownTacticsName(self.location(), name)),
-                },
-                Some(token) => Err(self.parse_error("A tactics name", token)),
-                None => Err(self.unexpected_end_of_file("A tactics name")),
+        let token = self.next_token();
+        let comments = if Self::starts_tactics(&token) { self.take_comments() } else { Vec::new() };
+        let tactics = match token {
+            Some(Token::CurlyBracketClose) => return Ok(OptionalTactics::None),
+            Some(Token::TacticsIdentifier(id)) => TacticsExpression::Variable(id, self.location()),
+            Some(Token::TodoKeyword) => TacticsExpression::Constant(Skipped {}.into_arc(), self.location()),
+            Some(Token::HypoKeyword) => TacticsExpression::Constant(Hypothesis {}.into_arc(), self.location()),
+            Some(Token::CurlyBracketOpen) => {
+                let (tactics, location) = self.parse_named_tactics()?;
+                TacticsExpression::Constant(tactics, location)
             },
-            Some(Token::WithKeyword) => Ok(OptionalTactics::With),
-            Some(token) => Err(self.parse_error(
+            Some(Token::WithKeyword) => return Ok(OptionalTactics::With),
+            Some(token) => return Err(self.parse_error(
                 "A tactics, within braces '{ ... }', or the '?' unfinished tactics.",
                 token,
             )),
-            None => Err(self.unexpected_end_of_file(
+            None => return Err(self.unexpected_end_of_file(
                 "A tactics, within braces '{ ... }', or the '?' unfinished tactics.",
             )),
+        };
+        Ok(OptionalTactics::Some(self.with_comments(tactics, comments)))
+    }
+
+    /// Parse a tactics within braces, after the opening brace, with the constructor registered for its name
+    fn parse_named_tactics(&mut self) -> Result<(Arc<dyn Tactics>, Location)> {
+        match self.next_token() {
+            Some(Token::Identifier(name)) => {
+                let location = self.location();
+                match self.registry.get(&name) {
+                    Some(constructor) => Ok((constructor(self)?, location)),
+                    None => Err(Error::UnknownTacticsName(location, name)),
+                }
+            },
+            Some(token) => Err(self.parse_error("A tactics name", token)),
+            None => Err(self.unexpected_end_of_file("A tactics name")),
         }
     }
 
@@ -344,6 +470,38 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a formula after its opening dollar sign, returning its math symbols as written, separated by single spaces
+    fn parse_mm_formula_text(&mut self) -> Result<String> {
+        let start = self.lexer.span().end;
+        self.parse_mm_formula()?;
+        // The lexer is now on the closing dollar sign
+        Ok(self.lexer.source()[start..self.lexer.span().start].split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// Parse a new theorem, its hypotheses within parens followed by its conclusion, like `( $ ph $ ) $ ( ps -> ph ) $`.
+    /// It is only added to the database when the script is executed.
+    pub fn parse_new_theorem(&mut self, name: String) -> Result<NewTheorem> {
+        let location = self.location();
+        // Theorems added when executing a previous script may be stated again
+        if self.new_theorems.contains(&name) || (self.db.get_theorem_label(name.clone()).is_some() && !self.db.is_added(&name)) {
+            return Err(Error::DuplicateLabel { location, label: name });
+        }
+        self.parse_token(Token::ParensOpen)?;
+        let mut hypotheses = vec![];
+        loop {
+            match self.next_token() {
+                Some(Token::ParensClose) => break,
+                Some(Token::FormulaStart) => hypotheses.push(self.parse_mm_formula_text()?),
+                Some(token) => return Err(self.parse_error("A hypothesis, within dollar signs '$ ... $', or a closing parens ')'", token)),
+                None => return Err(self.unexpected_end_of_file("A hypothesis, within dollar signs '$ ... $', or a closing parens ')'")),
+            }
+        }
+        self.parse_token(Token::FormulaStart)?;
+        let conclusion = self.parse_mm_formula_text()?;
+        self.new_theorems.insert(name.clone());
+        Ok(NewTheorem { name, hypotheses, conclusion })
+    }
+
     pub fn parse_formula_or_substvar(&mut self) -> Result<FormulaOrSubstitutionListId> {
         match self.next_token() {
             Some(Token::FormulaStart) => Ok(FormulaOrSubstitutionListId::Formula(self.parse_mm_formula()?)),
@@ -404,28 +562,27 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_optional_parameter(&mut self) -> Result<Option<Expression>> {
-        match self.next_token() {
-            Some(Token::TheoremIdentifier(id)) => Ok(Some(Expression::Statement(StatementExpression::Variable(id)))),
-            Some(Token::TacticsIdentifier(id)) => Ok(Some(Expression::Tactics(TacticsExpression::Variable(id)))),
-            Some(Token::FormulaIdentifier(id)) => Ok(Some(Expression::Formula(FormulaExpression::Variable(id)))),
-            Some(Token::TheoremLabel(name)) => Ok(Some(Expression::Statement(StatementExpression::Constant(self.get_theorem_label(name)?)))),
-            Some(Token::TodoKeyword) => Expression::tactics(Skipped {}.into_arc()),
-            Some(Token::HypoKeyword) => Expression::tactics(Hypothesis {}.into_arc()),
-            Some(Token::CurlyBracketOpen) => match self.next_token() {
-                Some(Token::Identifier(name)) => match name.as_ref() {
-                    "use" => Expression::tactics(UseScriptTactics::parse(self)?.into_arc()),
-                    "subgoal" => Expression::tactics(Subgoal::parse(self)?.into_arc()),
-                    "apply" => Expression::tactics(Apply::parse(self)?.into_arc()),
-                    "try" => Expression::tactics(Try::parse(self)?.into_arc()),
-                    "match" => Expression::tactics(Match::parse(self)?.into_arc()),
-                    "find" => Expression::tactics(Find::parse(self)?.into_arc()),
-                    "finddf" => Expression::tactics(FindDf::parse(self)?.into_arc()),
-                    "findhyp" => Expression::tactics(FindHyp::parse(self)?.into_arc()),
-                    _ => Err(Error::UnknownTacticsName(self.location(), name)),
-                },
-                Some(token) => Err(self.parse_error("A tactics name", token)),
-                None => Err(self.unexpected_end_of_file("A tactics name")),
+        let token = self.next_token();
+        let comments = if Self::starts_tactics(&token) { self.take_comments() } else { Vec::new() };
+        let tactics = match token {
+            Some(Token::TacticsIdentifier(id)) => TacticsExpression::Variable(id, self.location()),
+            Some(Token::TodoKeyword) => TacticsExpression::Constant(Skipped {}.into_arc(), self.location()),
+            Some(Token::HypoKeyword) => TacticsExpression::Constant(Hypothesis {}.into_arc(), self.location()),
+            Some(Token::CurlyBracketOpen) => {
+                let (tactics, location) = self.parse_named_tactics()?;
+                TacticsExpression::Constant(tactics, location)
             },
+            token => return self.parse_other_parameter(token),
+        };
+        Ok(Some(Expression::Tactics(self.with_comments(tactics, comments))))
+    }
+
+    /// Parse a parameter which is not a tactics, given its first token
+    fn parse_other_parameter(&mut self, token: Option<Token>) -> Result<Option<Expression>> {
+        match token {
+            Some(Token::TheoremIdentifier(id)) => Ok(Some(Expression::Statement(StatementExpression::Variable(id, self.location())))),
+            Some(Token::FormulaIdentifier(id)) => Ok(Some(Expression::Formula(FormulaExpression::Variable(id, self.location())))),
+            Some(Token::TheoremLabel(name)) => Ok(Some(Expression::Statement(self.parse_statement_label(name)?))),
             Some(Token::FormulaStart) => Ok(Some(Expression::Formula(FormulaExpression::Formula(self.parse_mm_formula()?)))),
             Some(Token::WithKeyword) => Ok(Some(Expression::SubstitutionList(SubstitutionListExpression::parse(self)?))),
             Some(Token::CurlyBracketClose) => Ok(None),
@@ -439,23 +596,49 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_commands(&mut self, tactics_definitions: &mut Vec<TacticsDefinition>, proof_definitions: &mut Vec<ProofDefinition>) -> Result {
+    fn parse_commands(&mut self, tactics_definitions: &mut Vec<TacticsDefinition>, proof_definitions: &mut Vec<ProofDefinition>, commands: &mut Vec<Command>) -> Result {
         loop {
-            match self.next_token() {
+            let token = self.next_token();
+            let mut comments = self.take_comments();
+            // Only tactics definitions keep their description, the other descriptive comments are kept as ordinary ones
+            if token != Some(Token::TacticsKeyword) {
+                if let Some(description) = self.last_description() {
+                    comments.push(Comment { text: description, trailing: false });
+                }
+            }
+            let kind = match token {
                 Some(Token::LoadKeyword) => {
                     let filename = self.parse_string_constant()?;
-                    self.db.parse(&filename)?; // TODO map_err to add the location of the error
+                    // A database already loaded, for example resolved by the language server, is kept
+                    if self.db.filename().is_none() {
+                        self.db.parse(&filename)?; // TODO map_err to add the location of the error
+                    }
+                    CommandKind::Load(filename)
+                }
+                Some(Token::IncludeKeyword) => {
+                    let filename = self.parse_string_constant()?;
+                    self.new_theorems = self.parse_included_file(filename.clone(), tactics_definitions, proof_definitions)?;
+                    CommandKind::Include(filename)
+                }
+                Some(Token::ImportKeyword) => {
+                    // Only the tactics definitions of imported files are kept, their proofs are ignored.
+                    let filename = self.parse_string_constant()?;
+                    self.parse_included_file(filename.clone(), tactics_definitions, &mut Vec::new())?;
+                    CommandKind::Import(filename)
                 }
-                // Some(Token::IncludeKeyword) => {
-                //     let filename = self.parse_string_constant()?;
-                //     let mut parser = Self::from_file(&filename)?;
-                //     parser.parse_commands(tactics_definitions, proof_definitions).with_context(|| format!("When parsing {}", filename))?;
-                // }
                 Some(Token::TacticsKeyword) => {
-                    tactics_definitions.push(TacticsDefinition::parse(self)?);
+                    let definition = TacticsDefinition::parse(self)?;
+                    let name = definition.name.clone();
+                    tactics_definitions.push(definition);
+                    CommandKind::Tactics(name)
                 }
                 Some(Token::ProofKeyword) => {
                     proof_definitions.push(ProofDefinition::parse(self)?);
+                    CommandKind::Proof(proof_definitions.len() - 1)
+                }
+                Some(Token::LemmaKeyword) => {
+                    proof_definitions.push(ProofDefinition::parse_lemma(self)?);
+                    CommandKind::Proof(proof_definitions.len() - 1)
                 }
                 Some(token) => {
                     return Err(self.parse_error(
@@ -464,21 +647,47 @@ impl<'a> Parser<'a> {
                     ));
                 }
                 None => {
+                    // Comments at the end of the file are kept for the next ones to be taken
+                    self.pending_comments = comments;
                     return Ok(());
                 }
-            }
+            };
+            commands.push(Command { comments, kind });
+        }
+    }
+
+    /// Parse an included file, returning the names of the new theorems stated so far, including the ones of the included file.
+    fn parse_included_file(&mut self, filename: String, tactics_definitions: &mut Vec<TacticsDefinition>, proof_definitions: &mut Vec<ProofDefinition>) -> Result<HashSet<String>> {
+        let location = self.location();
+        let path = self.resolve_path(&filename);
+        if self.include_stack.contains(&Self::canonical_path(&path)) {
+            return Err(Error::CircularInclude { location, filename });
         }
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(error) => return Err(Error::IncludeIoError { location, filename, error }),
+        };
+        let mut parser = self.included(path, &data);
+        // Only the commands of the main file are kept, to print it back
+        parser.parse_commands(tactics_definitions, proof_definitions, &mut Vec::new())
+            .map_err(|e| Error::IncludeParseError { error: Box::new(e), location, filename })?;
+        Ok(parser.new_theorems)
     }
 
     pub fn parse_script(&mut self) -> Result<Script> {
         let mut tactics_definitions = Vec::new();
         let mut proof_definitions = Vec::new();
-        self.parse_commands(&mut tactics_definitions, &mut proof_definitions)?;
-        Ok(Script::new(
+        let mut commands = Vec::new();
+        self.parse_commands(&mut tactics_definitions, &mut proof_definitions, &mut commands)?;
+        let mut script = Script::new(
             self.db.clone(),
             tactics_definitions,
             proof_definitions,
-        ))
+        );
+        script.registry = self.registry.clone();
+        script.commands = commands;
+        script.trailing_comments = self.take_comments();
+        Ok(script)
     }
 
     pub fn location(&self) -> Location {
@@ -528,3 +737,45 @@ pub trait Parse {
     where
         Self: Sized;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::script::Script;
+    use crate::tactics::TacticsRegistry;
+    use crate::test_support::TempPath;
+    use std::fs;
+    use std::sync::Arc;
+
+    fn parse(directory: &TempPath, filename: &str) -> crate::error::Result<Script> {
+        let path = directory.path().join(filename);
+        let text = fs::read_to_string(&path).unwrap();
+        Script::from_str(path.to_string_lossy().to_string(), &text, Arc::new(TacticsRegistry::default()))
+    }
+
+    #[test]
+    fn include() {
+        let directory = TempPath::dir("include", &[
+            ("main.rmm", "include \"lib/tactics.rmm\""),
+            ("lib/tactics.rmm", "tactics hyp ( ) { try ! ? }"),
+        ]);
+        let script = parse(&directory, "main.rmm").unwrap();
+        assert!(script.tactics_definitions.get("hyp".to_string()).is_some());
+    }
+
+    #[test]
+    fn circular_include() {
+        let directory = TempPath::dir("circular-include", &[
+            ("a.rmm", "include \"b.rmm\""),
+            ("b.rmm", "tactics hyp ( ) ! import \"a.rmm\""),
+        ]);
+        match parse(&directory, "a.rmm") {
+            Err(Error::IncludeParseError { error, filename, .. }) => {
+                assert_eq!(filename, "b.rmm");
+                assert!(matches!(*error, Error::CircularInclude { ref filename, .. } if filename == "a.rmm"));
+            },
+            Err(error) => panic!("Expected a circular inclusion, got {}", error),
+            Ok(_) => panic!("Expected a circular inclusion"),
+        }
+    }
+}
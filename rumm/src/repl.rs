@@ -0,0 +1,319 @@
+//! An interactive session, to develop proofs one tactics at a time
+use crate::context::{Context, WorkVariables};
+use crate::error::Result;
+use crate::lang::{DisplayPair, ProofStep};
+use crate::parser::{Parser, Token};
+use crate::script::Script;
+use annotate_snippets::display_list::DisplayList;
+use logos::Logos;
+use metamath_rs::Label;
+use std::io::{BufRead, Write};
+use typed_arena::Arena;
+
+const HELP: &str = "\
+Commands:
+  proof ~label   Start the proof of the given theorem
+  { ... }, !, ?  Prove the first open goal with the given tactics, using '?' for the goals left open
+  undo           Undo the last tactics
+  goals          Show the open goals
+  bindings       Show the work variables and metavariables bound
+  script         Print the proof script built so far
+  help           Show this help
+  quit           Leave the session";
+
+/// A goal of the proof, either still open, or proven by a tactics typed in the session
+struct Node {
+    context: Context,
+    proof: Option<NodeProof>,
+}
+
+/// The tactics proving a goal, the proof it found, and the goals it left open, one for each `?` of the tactics
+struct NodeProof {
+    tactics: String,
+    step: ProofStep,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn open(context: Context) -> Self {
+        Node { context, proof: None }
+    }
+
+    /// The paths of the goals still open, in the order of the proof
+    fn open_goals(&self, path: &mut Vec<usize>, goals: &mut Vec<Vec<usize>>) {
+        match &self.proof {
+            None => goals.push(path.clone()),
+            Some(proof) => {
+                for (index, child) in proof.children.iter().enumerate() {
+                    path.push(index);
+                    child.open_goals(path, goals);
+                    path.pop();
+                }
+            },
+        }
+    }
+
+    /// The goal at the given path, which goes through proven goals only
+    fn get(&self, path: &[usize]) -> &Node {
+        match path.split_first() {
+            Some((index, rest)) => self.proof.as_ref().expect("Path through an open goal").children[*index].get(rest),
+            None => self,
+        }
+    }
+
+    fn get_mut(&mut self, path: &[usize]) -> &mut Node {
+        match path.split_first() {
+            Some((index, rest)) => self.proof.as_mut().expect("Path through an open goal").children[*index].get_mut(rest),
+            None => self,
+        }
+    }
+
+    /// The proof of this goal, where the goals still open are skipped
+    fn proof_step(&self) -> ProofStep {
+        match &self.proof {
+            None => ProofStep::skipped(self.context.goal().clone()),
+            Some(proof) => fill_holes(&proof.step, &mut proof.children.iter().map(Node::proof_step)),
+        }
+    }
+
+    /// The tactics proving this goal, where the goals still open are left as `?`
+    fn script(&self) -> String {
+        match &self.proof {
+            None => "?".to_string(),
+            Some(proof) => {
+                let mut script = String::new();
+                let mut last = 0;
+                for (span, child) in todo_spans(&proof.tactics).into_iter().zip(&proof.children) {
+                    script.push_str(&proof.tactics[last..span.start]);
+                    script.push_str(&child.script());
+                    last = span.end;
+                }
+                script.push_str(&proof.tactics[last..]);
+                script
+            },
+        }
+    }
+}
+
+/// The positions of the `?` tactics in the given text
+fn todo_spans(text: &str) -> Vec<std::ops::Range<usize>> {
+    Token::lexer(text).spanned().filter(|(token, _)| *token == Token::TodoKeyword).map(|(_, span)| span).collect()
+}
+
+/// Replace the goals left unproven in the given proof by the given proofs, in order
+fn fill_holes(step: &ProofStep, fills: &mut dyn Iterator<Item = ProofStep>) -> ProofStep {
+    match step {
+        ProofStep::Apply { apply, apply_on, result, substitutions } => ProofStep::apply(
+            *apply,
+            apply_on.iter().map(|step| fill_holes(step, fills)).collect(),
+            result.clone(),
+            substitutions.clone(),
+        ),
+        ProofStep::Hyp { .. } => step.clone(),
+        ProofStep::Skipped { .. } => fills.next().unwrap_or_else(|| step.clone()),
+    }
+}
+
+/// A proof being developed
+struct Session {
+    theorem: Label,
+    root: Node,
+    work_variables: WorkVariables,
+    /// The goals proven so far, with the work variables before each of them, in order to undo them
+    history: Vec<(Vec<usize>, WorkVariables)>,
+    /// How much of the proof search traces has already been printed
+    printed: usize,
+}
+
+impl Session {
+    fn start(script: &Script, name: &str) -> Option<Self> {
+        let db = &script.db;
+        let theorem = db.get_theorem_label(name.trim_start_matches('~').to_string())?;
+        let (goal, hypotheses) = db.get_theorem_formulas(theorem)?;
+        let context = Context::new(db.clone(), Some(theorem), goal, hypotheses, script.tactics_definitions.clone(), &script.settings);
+        let work_variables = context.work_variables().clone();
+        Some(Session { theorem, root: Node::open(context), work_variables, history: vec![], printed: 0 })
+    }
+
+    fn open_goals(&self) -> Vec<Vec<usize>> {
+        let mut goals = vec![];
+        self.root.open_goals(&mut vec![], &mut goals);
+        goals
+    }
+
+    fn show(&self, script: &Script) {
+        let db = &script.db;
+        let goals = self.open_goals();
+        let path = match goals.first() {
+            Some(path) => path,
+            None => {
+                self.complete(script);
+                return;
+            },
+        };
+        let context = &self.root.get(path).context;
+        println!("Goal 1 of {}: {}", goals.len(), DisplayPair(&context.goal().substitute(self.work_variables.bindings()), db));
+        for (label, hyp) in context.hypotheses().iter() {
+            println!("  {} $e {}", DisplayPair(label, db), DisplayPair(hyp, db));
+        }
+        for (subgoal, _) in context.subgoals().iter() {
+            println!("  subgoal {}", DisplayPair(&subgoal.substitute(self.work_variables.bindings()), db));
+        }
+        for other in &goals[1..] {
+            println!("Open goal: {}", DisplayPair(&self.root.get(other).context.goal().substitute(self.work_variables.bindings()), db));
+        }
+    }
+
+    fn complete(&self, script: &Script) {
+        let step = self.root.proof_step().substitute(self.work_variables.bindings());
+        match step.check(&script.db) {
+            Ok(()) => println!("Proof complete:\n\n{}", self.script(script)),
+            Err(error) => println!("The proof is not valid: {}", error.message(&script.db)),
+        }
+    }
+
+    fn script(&self, script: &Script) -> String {
+        format!("proof ~{}\n{}\n", DisplayPair(&self.theorem, &script.db), self.root.script())
+    }
+
+    fn bindings(&self, script: &Script) {
+        let db = &script.db;
+        for (label, formula) in self.work_variables.bindings().iter() {
+            println!("  {} := {}", DisplayPair(label, db), DisplayPair(formula, db));
+        }
+        if let Some(path) = self.open_goals().first() {
+            for (label, formula) in self.root.get(path).context.variables().iter() {
+                println!("  {} := {}", DisplayPair(label, db), DisplayPair(formula, db));
+            }
+        }
+    }
+
+    /// Prove the first open goal with the given tactics
+    fn step(&mut self, script: &Script, text: &str) {
+        let db = &script.db;
+        let arena = Arena::new();
+        let tactics = match Parser::with_db_and_registry("<repl>".to_string(), text, db.clone(), script.registry().clone()).parse_tactics() {
+            Ok(tactics) => tactics,
+            Err(error) => {
+                println!("{}", DisplayList::from(error.to_snippet(&arena)));
+                return;
+            },
+        };
+        let path = match self.open_goals().into_iter().next() {
+            Some(path) => path,
+            None => {
+                println!("No goal left open");
+                return;
+            },
+        };
+        let node = self.root.get_mut(&path);
+        let mut context = node.context.clone();
+        context.commit_work_variables(&self.work_variables);
+        let recorded = context.record_holes();
+        let result = tactics.execute(&mut context);
+        let log = context.log();
+        eprint!("{}", &log[self.printed.min(log.len())..]);
+        self.printed = log.len();
+        let step = match result {
+            Ok(step) => step,
+            Err(error) => {
+                println!("{}", DisplayList::from(error.to_snippet("The tactics failed".to_string(), db, &arena)));
+                return;
+            },
+        };
+        let goals = step.skipped_goals();
+        // Each goal left open shall come from one `?` of the tactics, so that the script can be built
+        let todos = todo_spans(text).len();
+        if goals.len() != todos {
+            println!("The tactics leaves {} goals open, but contains {} '?': use '?' only for the goals left open", goals.len(), todos);
+            return;
+        }
+        let bindings = context.work_variables().bindings();
+        let recorded = recorded.lock().unwrap();
+        let children = goals.iter().map(|goal| {
+            let goal = goal.substitute(bindings);
+            let hole = recorded.iter().rev().find(|hole| hole.goal().substitute(bindings) == goal);
+            Node::open(hole.cloned().unwrap_or_else(|| context.with_goal(goal)))
+        }).collect();
+        node.proof = Some(NodeProof { tactics: text.trim().to_string(), step, children });
+        self.history.push((path, std::mem::replace(&mut self.work_variables, context.work_variables().clone())));
+    }
+
+    fn undo(&mut self) {
+        match self.history.pop() {
+            Some((path, work_variables)) => {
+                self.root.get_mut(&path).proof = None;
+                self.work_variables = work_variables;
+            },
+            None => println!("Nothing to undo"),
+        }
+    }
+}
+
+/// Read a command, continued over several lines until its braces are balanced
+fn read_command(input: &mut impl BufRead) -> Option<String> {
+    let mut command = String::new();
+    let mut prompt = "rumm> ";
+    loop {
+        print!("{}", prompt);
+        std::io::stdout().flush().ok()?;
+        if input.read_line(&mut command).ok()? == 0 {
+            return None;
+        }
+        if command.matches('{').count() <= command.matches('}').count() {
+            return Some(command.trim().to_string());
+        }
+        prompt = "...   ";
+    }
+}
+
+/// Run an interactive session, using the database and the tactics definitions of the given script
+pub fn run(script: &Script) -> Result {
+    println!("{}", HELP);
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut session: Option<Session> = None;
+    while let Some(command) = read_command(&mut input) {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => continue,
+            ["quit"] | ["exit"] => break,
+            ["help"] => {
+                println!("{}", HELP);
+                continue;
+            },
+            ["proof", name] => {
+                session = Session::start(script, name);
+                if session.is_none() {
+                    println!("Unknown theorem {}", name);
+                }
+            },
+            _ => {
+                let session = match &mut session {
+                    Some(session) => session,
+                    None => {
+                        println!("Start a proof first, with 'proof ~label'");
+                        continue;
+                    },
+                };
+                match words.as_slice() {
+                    ["undo"] => session.undo(),
+                    ["goals"] => {},
+                    ["bindings"] => {
+                        session.bindings(script);
+                        continue;
+                    },
+                    ["script"] => {
+                        print!("{}", session.script(script));
+                        continue;
+                    },
+                    _ => session.step(script, &command),
+                }
+            },
+        }
+        if let Some(session) = &session {
+            session.show(script);
+        }
+    }
+    Ok(())
+}
@@ -1,13 +1,50 @@
-use crate::error::Result;
-use crate::lang::{Db, Display};
-use crate::lang::{ProofDefinition, TacticsDefinition, TacticsDict};
+use annotate_snippets::display_list::DisplayList;
+use crate::context::{Context, Settings};
+use crate::error::{Error, Result};
+use crate::lang::{Command, CommandKind, Comment, Db, Display, DisplayPair, MmSource, NewTheorem, ProofStep, format_command_comments};
+use crate::lang::{Hypotheses, ProofDefinition, TacticsDefinition, TacticsDict, TacticsExpression};
 use crate::parser::{Parse, Parser};
+use crate::summary::{Outcome, ProofOutcome};
+use crate::tactics::{TacticsError, TacticsRegistry, TacticsResult};
 use core::fmt::{Debug, Formatter};
+use metamath_rs::{Formula, Label};
+use metamath_rs::proof::ProofTreeArray;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use typed_arena::Arena;
+
+/// Where the proofs found are written, in addition to the mmp output.
+pub enum ProofOutput {
+    /// Only print the proofs in the mmp format on the standard output
+    Mmp,
+    /// Also rewrite the proofs in the loaded database file, or in a copy of it if a file name is given
+    Database(Option<String>),
+}
+
+/// The outcome of a proof search: the proof found, along with its proof tree when it is complete
+type ProofResult = std::result::Result<(ProofStep, Option<ProofTreeArray>), TacticsError>;
+
+/// The results of the metamath-rs verifier for the proofs of a script
+struct Verification {
+    /// Whether each proof was verified, which requires it to be complete, and its statement to be found in the database source
+    verified: Vec<bool>,
+    /// The diagnostics of the proofs found invalid, by theorem label
+    diagnostics: HashMap<String, String>,
+}
 
 pub struct Script {
     pub(crate) db: Db,
     pub(crate) tactics_definitions: TacticsDict,
     pub(crate) proof_definitions: Vec<ProofDefinition>,
+    pub(crate) settings: Settings,
+    /// The tactics registry the script was parsed with, also used to parse single tactics
+    pub(crate) registry: Arc<TacticsRegistry>,
+    /// The commands of the main file, to print it back
+    pub(crate) commands: Vec<Command>,
+    /// The comments following the last command of the main file
+    pub(crate) trailing_comments: Vec<Comment>,
 }
 
 impl Debug for Script {
@@ -25,6 +62,29 @@ impl Debug for Script {
     }
 }
 
+/// The main file of the script, printed back with its comments, one blank line between commands
+impl Display for Script {
+    fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
+        for (index, command) in self.commands.iter().enumerate() {
+            if format_command_comments(&command.comments, index > 0, fmt)? {
+                fmt.write_str("\n")?;
+            }
+            match &command.kind {
+                CommandKind::Load(filename) => write!(fmt, "load \"{}\"", filename)?,
+                CommandKind::Include(filename) => write!(fmt, "include \"{}\"", filename)?,
+                CommandKind::Import(filename) => write!(fmt, "import \"{}\"", filename)?,
+                CommandKind::Tactics(name) => if let Some(definition) = self.tactics_definitions.get(name.clone()) {
+                    fmt.write_str(DisplayPair(definition, db).to_string().trim_end())?;
+                },
+                CommandKind::Proof(index) => fmt.write_str(DisplayPair(&self.proof_definitions[*index], db).to_string().trim_end())?,
+            }
+        }
+        let separate = self.trailing_comments.iter().any(|c| !c.trailing) && !self.commands.is_empty();
+        format_command_comments(&self.trailing_comments, separate, fmt)?;
+        fmt.write_str("\n")
+    }
+}
+
 impl Parse for Script {
     fn parse(parser: &mut Parser) -> Result<Self> {
         parser.parse_script()
@@ -41,28 +101,253 @@ impl Script {
             db,
             tactics_definitions: TacticsDict::from(tactics_list),
             proof_definitions,
+            settings: Settings::default(),
+            registry: Arc::new(TacticsRegistry::default()),
+            commands: Vec::new(),
+            trailing_comments: Vec::new(),
         }
     }
 
-    pub fn from_str(filename: String, str: &str) -> Result<Self> {
-        Script::parse(&mut Parser::from_str(filename, str))
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    /// Parse a script, whose tactics are looked up in the given registry
+    pub fn from_str(filename: String, str: &str, registry: Arc<TacticsRegistry>) -> Result<Self> {
+        Script::parse(&mut Parser::with_registry(filename, str, registry))
+    }
+
+    /// Parse a script using an already loaded database, which is not loaded again by its `load` command,
+    /// and whose tactics are looked up in the given registry
+    pub fn from_str_with_db(filename: String, str: &str, db: Db, registry: Arc<TacticsRegistry>) -> Result<Self> {
+        Script::parse(&mut Parser::with_db_and_registry(filename, str, db, registry))
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// The tactics registry the script was parsed with
+    pub fn registry(&self) -> &Arc<TacticsRegistry> {
+        &self.registry
+    }
+
+    /// Parse a single tactics, like `{ apply ~ax-mp ! ! }`, which may use the tactics definitions of this script.
+    /// The tactics are looked up in the registry the script was parsed with.
+    pub fn parse_tactics(&self, str: &str) -> Result<TacticsExpression> {
+        Parser::with_db_and_registry("<tactics>".to_string(), str, self.db.clone(), self.registry.clone()).parse_tactics()
+    }
+
+    /// Parse a formula, within dollar signs, like `$ ( ph -> ph ) $`
+    pub fn parse_formula(&self, str: &str) -> Result<Formula> {
+        Parser::with_db("<formula>".to_string(), str, self.db.clone()).parse_formula()
+    }
+
+    /// Run the tactics on the given goal, with the given essential hypotheses, without printing anything.
+    /// When a theorem is given, only the statements preceding it are used.
+    /// The proof found has its work variables resolved, and may still contain skipped steps.
+    pub fn prove(&self, tactics: &TacticsExpression, goal: Formula, hypotheses: Hypotheses, theorem: Option<Label>) -> TacticsResult {
+        let mut context = Context::new(self.db.clone(), theorem, goal, hypotheses, self.tactics_definitions.clone(), &self.settings);
+        tactics.execute(&mut context)
+            .map(|step| step.substitute(context.work_variables().bindings()))
     }
 
-    pub fn execute(&mut self) -> Result {
-        for proof_def in &self.proof_definitions {
-            match proof_def.prove(self.db.clone(), self.tactics_definitions.clone()) {
-                Ok(step) => {
-                    println!("Success");
-                    let mut arr = step.as_proof_tree_array(self.db.clone());
-                    arr.calc_indent();
+    /// Check the whole script before running any proof:
+    /// all script tactics used shall be defined, called with the right parameters, and all variables shall be bound.
+    /// Returns all the problems found, in source order.
+    pub fn check(&self) -> Vec<Error> {
+        let mut errors = vec![];
+        for tactics_definition in self.tactics_definitions.iter() {
+            errors.extend(tactics_definition.check(&self.tactics_definitions));
+        }
+        for proof_definition in &self.proof_definitions {
+            errors.extend(proof_definition.check(&self.tactics_definitions));
+        }
+        errors.sort_by_key(|error| error.location().map(|location| (location.filename.clone(), location.line_number, location.start)));
+        errors
+    }
+
+    /// Search all the proofs, write them to `out`, and return the outcome of each of them, in source order.
+    /// The traces of the proof searches are written to `traces`, nothing is printed on the standard output or error.
+    pub fn execute(&mut self, output: &ProofOutput, out: &mut dyn Write, traces: &mut dyn Write) -> Result<Vec<ProofOutcome>> {
+        // The theorems stated by the script are added to the database once, before searching the proofs
+        let new_theorems: Vec<NewTheorem> = self.proof_definitions.iter().filter_map(|proof_def| proof_def.new_theorem().cloned()).collect();
+        self.db.set_additions(&new_theorems)?;
+        let theorems = self.proof_definitions.iter()
+            .map(|proof_def| proof_def.theorem(&self.db).ok_or_else(|| Error::TheoremNotAdded {
+                location: Some(proof_def.location().clone()),
+                name: proof_def.new_theorem().map_or_else(String::new, |theorem| theorem.name.clone()),
+            }))
+            .collect::<Result<Vec<Label>>>()?;
+        let (proofs, durations) = self.search_proofs(&theorems, traces)?;
+        let verification = self.verify_proofs(&theorems, &proofs)?;
+        let mut source = match output {
+            ProofOutput::Mmp => None,
+            ProofOutput::Database(_) => Some(self.db.source()?),
+        };
+        if let Some(source) = &mut source {
+            // Invalid proofs are not written
+            let is_valid = |theorem: Label| !verification.diagnostics.contains_key(&theorem.to_string(&self.db));
+            self.append_new_theorems(source, &theorems, &proofs, is_valid, |theorem, arr| self.db.compressed_proof(theorem, arr));
+        }
+        let outcomes = self.report(&theorems, &proofs, &verification, durations, source.as_mut(), out)?;
+        if let (Some(source), ProofOutput::Database(filename)) = (&source, output) {
+            source.write(filename.as_deref())?;
+        }
+        if let Some(cache) = &self.settings.cache {
+            writeln!(out, "{}", cache.statistics())?;
+        }
+        Ok(outcomes)
+    }
+
+    /// Search the proofs of the given theorems, returning them with the time spent on each of them.
+    /// Proofs are searched in parallel, and their logs are written to `traces` in source order as soon as they are available.
+    /// Each lemma ends a batch of proofs, since it shall be proven before the following proofs may use it.
+    fn search_proofs(&self, theorems: &[Label], traces: &mut dyn Write) -> Result<(Vec<ProofResult>, Vec<Duration>)> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.settings.jobs)
+            .build()
+            .map_err(Error::ThreadPool)?;
+        let mut proofs = vec![];
+        let mut durations: Vec<Duration> = vec![];
+        let mut written = Ok(());
+        while proofs.len() < self.proof_definitions.len() {
+            let first = proofs.len();
+            let end = self.proof_definitions[first..].iter().position(ProofDefinition::is_lemma)
+                .map_or(self.proof_definitions.len(), |index| first + index + 1);
+            let (sender, receiver) = mpsc::channel();
+            pool.in_place_scope(|scope| {
+                for (index, (proof_def, theorem)) in self.proof_definitions[first..end].iter().zip(&theorems[first..end]).enumerate() {
+                    let sender = sender.clone();
+                    let (db, tactics_definitions, settings) = (&self.db, &self.tactics_definitions, &self.settings);
+                    scope.spawn(move |_| {
+                        let start = Instant::now();
+                        let (proof, log) = proof_def.prove(*theorem, db.clone(), tactics_definitions.clone(), settings);
+                        let proof = proof.and_then(|step| {
+                            step.check(db)?;
+                            if step.is_complete() {
+                                let mut arr = step.as_proof_tree_array(db.clone())?;
+                                arr.calc_indent();
+                                Ok((step, Some(arr)))
+                            } else {
+                                Ok((step, None))
+                            }
+                        });
+                        // The receiver only goes away once all proofs have been received
+                        sender.send((first + index, proof, log, start.elapsed())).unwrap();
+                    });
+                }
+                drop(sender);
+                let mut pending = BTreeMap::new();
+                for (index, proof, log, duration) in receiver.iter() {
+                    pending.insert(index, (proof, log, duration));
+                    while let Some((proof, log, duration)) = pending.remove(&proofs.len()) {
+                        // All proofs are still received after a write error, their threads would fail otherwise
+                        if written.is_ok() {
+                            written = traces.write_all(log.as_bytes());
+                        }
+                        proofs.push(proof);
+                        durations.push(duration);
+                    }
+                }
+            });
+            written?;
+            let last = &self.proof_definitions[end - 1];
+            if last.is_lemma() && matches!(proofs[end - 1], Ok((_, Some(_)))) {
+                self.db.publish(theorems[end - 1]);
+                if let Some(cache) = &self.settings.cache {
+                    cache.clear_failures();
+                }
+            }
+        }
+        Ok((proofs, durations))
+    }
+
+    /// Have all the complete proofs found checked by the metamath-rs verifier.
+    /// They are checked in the normal format, so that the diagnostics show the label of the failing step.
+    fn verify_proofs(&self, theorems: &[Label], proofs: &[ProofResult]) -> Result<Verification> {
+        let mut checked_source = self.db.source()?;
+        self.append_new_theorems(&mut checked_source, theorems, proofs, |_| true, |theorem, arr| self.db.normal_proof(theorem, arr));
+        let mut verified = vec![];
+        for (theorem, proof) in theorems.iter().zip(proofs) {
+            verified.push(match proof {
+                Ok((_, Some(arr))) => checked_source.set_proof(&theorem.to_string(&self.db), &self.db.normal_proof(*theorem, arr)),
+                _ => false,
+            });
+        }
+        let labels: Vec<String> = theorems.iter().zip(&verified)
+            .filter(|(_, verified)| **verified)
+            .map(|(theorem, _)| theorem.to_string(&self.db))
+            .collect();
+        let diagnostics = if labels.is_empty() { HashMap::new() } else { self.db.verify(&checked_source, &labels)? };
+        Ok(Verification { verified, diagnostics })
+    }
+
+    /// Write the result of each proof to `out`, along with the proof itself when one was found,
+    /// set the valid proofs in the source if one is given, and return the outcomes of the proofs.
+    fn report(&self, theorems: &[Label], proofs: &[ProofResult], verification: &Verification, durations: Vec<Duration>, mut source: Option<&mut MmSource>, out: &mut dyn Write) -> Result<Vec<ProofOutcome>> {
+        writeln!(out, "====================================================\n\n")?;
+        let mut outcomes = vec![];
+        for ((((proof_def, theorem), proof), verified), duration) in self.proof_definitions.iter().zip(theorems.iter().copied()).zip(proofs).zip(&verification.verified).zip(durations) {
+            let label = theorem.to_string(&self.db);
+            let outcome = match proof {
+                Ok((step, None)) => {
+                    writeln!(out, "Proof for {}: Incomplete", label)?;
+                    step.export_incomplete_mmp(theorem, &self.db, out)?;
+                    Outcome::Skipped
+                }
+                Ok((step, Some(arr))) => {
+                    if let Some(diagnostic) = verification.diagnostics.get(&label) {
+                        writeln!(out, "Proof for {}: Invalid proof\n{}", label, diagnostic)?;
+                        outcomes.push(ProofOutcome { label, outcome: Outcome::Invalid(diagnostic.clone()), duration });
+                        continue;
+                    }
+                    if *verified {
+                        writeln!(out, "Proof for {}: Success", label)?;
+                    } else {
+                        writeln!(out, "Proof for {}: Success, but not verified: statement not found in the database source", label)?;
+                    }
                     self.db
-                        .export_mmp(proof_def.theorem(), &arr, &mut std::io::stdout());
+                        .export_mmp(theorem, arr, out);
+                    if let Some(new_theorem) = proof_def.new_theorem() {
+                        writeln!(out, "{}", new_theorem.to_mm(&self.db, &self.db.compressed_proof(theorem, arr), Some(step)))?;
+                    }
+                    if let Some(source) = &mut source {
+                        if source.is_modification_discouraged(&label) {
+                            writeln!(out, "Proof modification is discouraged for {}, proof not written", label)?;
+                        } else if !source.set_proof(&label, &self.db.compressed_proof(theorem, arr)) {
+                            writeln!(out, "Statement {} not found in the database source, proof not written", label)?;
+                        }
+                    }
+                    if *verified { Outcome::Success } else { Outcome::Unverified }
+                }
+                Err(error @ TacticsError::MissingSubstitution(..)) | Err(error @ TacticsError::InvalidStep(..)) => {
+                    writeln!(out, "Proof for {}: Invalid proof, {}", label, error.message(&self.db))?;
+                    Outcome::Invalid(error.message(&self.db))
                 }
-                Err(_) => {
-                    println!("Failure");
+                Err(error) => {
+                    writeln!(out, "Proof for {}: Failure", label)?;
+                    let arena = Arena::new();
+                    writeln!(out, "{}", DisplayList::from(error.to_snippet(format!("No proof found for {}", label), &self.db, &arena)))?;
+                    Outcome::Failure(error.root_cause().message(&self.db))
                 }
+            };
+            outcomes.push(ProofOutcome { label, outcome, duration });
+        }
+        Ok(outcomes)
+    }
+
+    /// Append the theorems stated by the script at the end of the given source, in order.
+    /// The proofs found complete and accepted are given in the style provided, the other theorems are left without proof.
+    fn append_new_theorems(&self, source: &mut MmSource, theorems: &[Label], proofs: &[ProofResult], accept: impl Fn(Label) -> bool, style: impl Fn(Label, &ProofTreeArray) -> String) {
+        for ((proof_def, theorem), proof) in self.proof_definitions.iter().zip(theorems).zip(proofs) {
+            if let Some(new_theorem) = proof_def.new_theorem() {
+                let mm = match proof {
+                    Ok((step, Some(arr))) if accept(*theorem) => new_theorem.to_mm(&self.db, &style(*theorem, arr), Some(step)),
+                    _ => new_theorem.to_mm(&self.db, "?", None),
+                };
+                source.append(&format!("\n{}", mm));
             }
         }
-        Ok(())
     }
 }
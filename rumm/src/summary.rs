@@ -0,0 +1,157 @@
+//! The outcome of each proof of a run, and the summary files written from them
+use crate::error::Result;
+use std::time::Duration;
+
+/// How a proof ended
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// A complete proof was found, and accepted by the verifier
+    Success,
+    /// A complete proof was found, but the statement could not be found in the database source to verify it
+    Unverified,
+    /// The proof is incomplete, some goals were skipped with `?`
+    Skipped,
+    /// The proof found was rejected, for the given reason
+    Invalid(String),
+    /// No proof was found, for the given reason
+    Failure(String),
+}
+
+impl Outcome {
+    /// Whether this outcome shall make the run fail
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Outcome::Invalid(_) | Outcome::Failure(_))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Unverified => "unverified",
+            Outcome::Skipped => "skipped",
+            Outcome::Invalid(_) => "invalid",
+            Outcome::Failure(_) => "failure",
+        }
+    }
+
+    fn reason(&self) -> Option<&str> {
+        match self {
+            Outcome::Invalid(reason) | Outcome::Failure(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of the proof of one theorem, and the time spent searching and checking it
+#[derive(Clone, Debug)]
+pub struct ProofOutcome {
+    pub label: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+/// The format of a summary file
+#[derive(Clone, Copy, Debug)]
+pub enum SummaryFormat {
+    Json,
+    JUnit,
+}
+
+impl SummaryFormat {
+    /// The format for the given file name: JUnit XML for `.xml` files, JSON otherwise
+    pub fn from_filename(filename: &str) -> Self {
+        if filename.ends_with(".xml") { SummaryFormat::JUnit } else { SummaryFormat::Json }
+    }
+}
+
+/// Write the outcomes of a run into the given file
+pub fn write(outcomes: &[ProofOutcome], filename: &str, format: SummaryFormat) -> Result {
+    let summary = match format {
+        SummaryFormat::Json => to_json(outcomes),
+        SummaryFormat::JUnit => to_junit(outcomes),
+    };
+    std::fs::write(filename, summary)?;
+    Ok(())
+}
+
+fn to_json(outcomes: &[ProofOutcome]) -> String {
+    let proofs: Vec<_> = outcomes.iter().map(|proof| serde_json::json!({
+        "label": proof.label,
+        "outcome": proof.outcome.name(),
+        "reason": proof.outcome.reason(),
+        "seconds": proof.duration.as_secs_f64(),
+    })).collect();
+    let count = |name| outcomes.iter().filter(|proof| proof.outcome.name() == name).count();
+    format!("{:#}\n", serde_json::json!({
+        "proofs": proofs,
+        "success": count("success"),
+        "unverified": count("unverified"),
+        "skipped": count("skipped"),
+        "invalid": count("invalid"),
+        "failure": count("failure"),
+        "seconds": outcomes.iter().map(|proof| proof.duration).sum::<Duration>().as_secs_f64(),
+    }))
+}
+
+fn to_junit(outcomes: &[ProofOutcome]) -> String {
+    let failures = outcomes.iter().filter(|proof| proof.outcome.is_failure()).count();
+    // Proofs which could not be verified are not counted as passed
+    let skipped = outcomes.iter().filter(|proof| matches!(proof.outcome, Outcome::Skipped | Outcome::Unverified)).count();
+    let seconds = outcomes.iter().map(|proof| proof.duration).sum::<Duration>().as_secs_f64();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuite name=\"rumm\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n", outcomes.len(), failures, skipped, seconds));
+    for proof in outcomes {
+        xml.push_str(&format!("  <testcase name=\"{}\" classname=\"rumm\" time=\"{:.3}\"", escape(&proof.label), proof.duration.as_secs_f64()));
+        match &proof.outcome {
+            Outcome::Success => xml.push_str("/>\n"),
+            Outcome::Unverified => xml.push_str(">\n    <skipped message=\"Proof not verified, statement not found in the database source\"/>\n  </testcase>\n"),
+            Outcome::Skipped => xml.push_str(">\n    <skipped message=\"Incomplete proof\"/>\n  </testcase>\n"),
+            Outcome::Invalid(reason) | Outcome::Failure(reason) => xml.push_str(&format!(
+                ">\n    <failure type=\"{}\" message=\"{}\"/>\n  </testcase>\n",
+                proof.outcome.name(),
+                escape(reason),
+            )),
+        }
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_json, to_junit, Outcome, ProofOutcome};
+    use std::time::Duration;
+
+    fn outcomes() -> Vec<ProofOutcome> {
+        vec![
+            ("a", Outcome::Success),
+            ("b", Outcome::Unverified),
+            ("c", Outcome::Skipped),
+            ("d", Outcome::Invalid("Wrong <step>".to_string())),
+            ("e", Outcome::Failure("No match found".to_string())),
+        ].into_iter().map(|(label, outcome)| ProofOutcome { label: label.to_string(), outcome, duration: Duration::from_millis(500) }).collect()
+    }
+
+    #[test]
+    fn json() {
+        let json: serde_json::Value = serde_json::from_str(&to_json(&outcomes())).unwrap();
+        for (count, expected) in [("success", 1), ("unverified", 1), ("skipped", 1), ("invalid", 1), ("failure", 1)] {
+            assert_eq!(json[count], expected, "{}", count);
+        }
+        assert_eq!(json["proofs"][1]["outcome"], "unverified");
+        assert_eq!(json["proofs"][3]["reason"], "Wrong <step>");
+        assert_eq!(json["seconds"], 2.5);
+    }
+
+    #[test]
+    fn junit() {
+        let xml = to_junit(&outcomes());
+        assert!(xml.contains("<testsuite name=\"rumm\" tests=\"5\" failures=\"2\" skipped=\"2\" time=\"2.500\">"));
+        assert!(xml.contains("<testcase name=\"a\" classname=\"rumm\" time=\"0.500\"/>"));
+        assert!(xml.contains("<testcase name=\"b\" classname=\"rumm\" time=\"0.500\">\n    <skipped message=\"Proof not verified"));
+        assert!(xml.contains("<failure type=\"invalid\" message=\"Wrong &lt;step&gt;\"/>"));
+    }
+}
@@ -2,13 +2,14 @@ use crate::lang::DisplayPair;
 use crate::lang::SubstitutionListExpression;
 use metamath_rs::formula::Substitutions;
 use crate::lang::TacticsExpression;
+use crate::check::CheckScope;
 use crate::context::Context;
 use crate::error::Result;
 use crate::lang::ProofStep;
-use crate::lang::{Db, Display};
+use crate::lang::{Db, Display, Hypotheses};
 use crate::lang::StatementExpression;
 use crate::parser::{Parse, Parser, OptionalTactics};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
@@ -24,12 +25,17 @@ pub struct Apply {
 
 impl Display for Apply {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Apply \n")?;
+        fmt.write_str("{ apply ")?;
         self.theorem.format(fmt, db)?;
         for t in &self.subtactics {
+            fmt.write_str(" ")?;
             t.format(fmt, db)?;
         }
-        fmt.write_str("}\n")
+        if !self.substitutions.is_empty() {
+            fmt.write_str(" with ")?;
+            self.substitutions.format(fmt, db)?;
+        }
+        fmt.write_str(" }")
     }
 }
 
@@ -66,10 +72,20 @@ impl Tactics for Apply {
         "A tactics which applies a given theorem to prove the goal.".to_string()
     }
 
+    fn check(&self, scope: &mut CheckScope) {
+        self.theorem.check(scope);
+        for t in &self.subtactics {
+            t.check(scope);
+        }
+        self.substitutions.check(scope);
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         context.enter(&format!("Apply {}", DisplayPair(&self.theorem, &context.db)));
-        // context.db.debug_formula(context.goal());
-        // println!("  vars:{}", DisplayPair(context.variables(), &context.db));
         let mut my_subst = Substitutions::default();
         for (l,f) in self.substitutions.evaluate(context)?.iter() {
             context.message(format!("Subst: {} {}", DisplayPair(l, &context.db), DisplayPair(f, &context.db)).as_str());
@@ -85,29 +101,61 @@ impl Tactics for Apply {
                 return Err(e.into());
             }
             subst.extend(&my_subst);
-            // context.message(&format!("  subst:{}", DisplayPair(&subst, &context.db)));
             if hyps.len() == self.subtactics.len() {
-                let mut substeps = vec![];
-                // TODO check count!
-                for ((_hyp_label, hyp_formula), tactics) in hyps.iter().zip(&self.subtactics) {
-                    let sub_goal = hyp_formula.substitute(&subst);
-                    let mut sub_context = context.with_goal(sub_goal);
-                    substeps.push(tactics.execute(&mut sub_context)?);
+                // Variables not determined by the goal nor by the substitutions given are replaced by work variables
+                let mut apply_context = context.clone();
+                for variable in context.db.floating_hypotheses(theorem).unwrap_or_default() {
+                    if subst.get(variable).is_none() {
+                        let work_variable = apply_context.new_work_variable(variable)?;
+                        subst.insert(variable, work_variable);
+                    }
                 }
-                context.exit("Apply Unification success");
-                Ok(ProofStep::apply(
-                    theorem,
-                    substeps.into_boxed_slice(),
-                    context.goal().clone(),
-                    Box::new(subst),
-                ))
+                let goal = context.goal().clone();
+                self.prove_hypotheses(&hyps, &subst, 0, &mut apply_context, vec![], &mut |substeps, apply_context| {
+                    context.exit("Apply Unification success");
+                    let step = ProofStep::apply(
+                        theorem,
+                        substeps.into_boxed_slice(),
+                        apply_context.resolve(&goal),
+                        Box::new(subst.clone()),
+                    );
+                    then(step, apply_context)
+                })
             } else {
                 context.exit("Apply Hyps don't match");
-                Err(TacticsError::WrongHypCount(self.subtactics.len(), hyps.len()))
+                Err(TacticsError::WrongHypCount(self.subtactics.len(), hyps.len(), self.theorem.location().clone()))
             }
         } else {
             context.exit("Unknown theorem label");
-            Err(TacticsError::UnknownLabel(theorem))
+            Err(TacticsError::UnknownLabel(theorem, Some(self.theorem.location().clone())))
+        }
+    }
+}
+
+impl Apply {
+    /// Prove the hypotheses of the theorem applied, starting with the one at `index`,
+    /// and pass the proofs found to the continuation.
+    /// When backtracking, a failure to prove a hypothesis makes the preceding ones try their next alternative.
+    fn prove_hypotheses(
+        &self,
+        hyps: &Hypotheses,
+        subst: &Substitutions,
+        index: usize,
+        context: &mut Context,
+        substeps: Vec<ProofStep>,
+        then: &mut dyn FnMut(Vec<ProofStep>, &mut Context) -> TacticsResult,
+    ) -> TacticsResult {
+        if index == hyps.len() {
+            return then(substeps, context);
         }
+        let sub_goal = context.resolve(&hyps[index].1.substitute(subst));
+        let mut sub_context = context.with_goal(sub_goal);
+        self.subtactics[index].execute_then(&mut sub_context, &mut |step, sub_context| {
+            let mut next_context = context.clone();
+            next_context.commit_work_variables(sub_context.work_variables());
+            let mut substeps = substeps.clone();
+            substeps.push(step);
+            self.prove_hypotheses(hyps, subst, index + 1, &mut next_context, substeps, then)
+        })
     }
 }
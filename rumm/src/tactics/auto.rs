@@ -0,0 +1,242 @@
+use metamath_rs::as_str;
+use metamath_rs::formula::Substitutions;
+use metamath_rs::{Formula, Label};
+use crate::check::CheckScope;
+use crate::context::Context;
+use crate::error::Result;
+use crate::lang::{Db, Display, DisplayPair, Hypotheses, ProofStep, StatementExpression};
+use crate::parser::{Parse, Parser, Token};
+use crate::tactics::{execute_first, try_alternative, Continuation, Tactics};
+use crate::tactics::TacticsError;
+use crate::tactics::TacticsResult;
+use core::fmt::Formatter;
+use std::cell::Cell;
+
+/// The maximum number of goals the `auto` tactics considers, over all search depths, unless set otherwise.
+const DEFAULT_BUDGET: usize = 10000;
+
+/// A continuation of the search, to which the proofs found for a goal are passed, with the context binding their work variables
+type Found<'b> = dyn FnMut(ProofStep, &Context) -> TacticsResult + 'b;
+
+/// A statement, or a family of statements, the `auto` tactics may apply
+pub enum AutoStatement {
+    Statement(StatementExpression),
+    Prefix(String),
+}
+
+/// A tactics which searches for a combination of the given statements proving the goal,
+/// by iterative deepening up to a given depth.
+///
+pub struct Auto {
+    depth: usize,
+    statements: Vec<AutoStatement>,
+}
+
+impl Display for Auto {
+    fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
+        fmt.write_str(&format!("{{ auto {} ", self.depth))?;
+        for s in &self.statements {
+            match s {
+                AutoStatement::Statement(s) => s.format(fmt, db)?,
+                AutoStatement::Prefix(prefix) => fmt.write_str(&format!("\"{}\"", prefix))?,
+            }
+            fmt.write_str(" ")?;
+        }
+        fmt.write_str("}")
+    }
+}
+
+impl Parse for Auto {
+    fn parse(parser: &mut Parser) -> Result<Self> {
+        let depth = parser.parse_number("A search depth")?;
+        let mut statements = Vec::new();
+        loop {
+            match parser.next_token() {
+                Some(Token::CurlyBracketClose) => break,
+                Some(Token::TheoremLabel(name)) => statements.push(AutoStatement::Statement(parser.parse_statement_label(name)?)),
+                Some(Token::TheoremIdentifier(id)) => statements.push(AutoStatement::Statement(StatementExpression::Variable(id, parser.location()))),
+                Some(Token::StringConstant(prefix)) => statements.push(AutoStatement::Prefix(prefix)),
+                Some(token) => return Err(parser.parse_error("A theorem label, or a label prefix within quotes", token)),
+                None => return Err(parser.unexpected_end_of_file("A theorem label, or a label prefix within quotes")),
+            }
+        }
+        Ok(Auto { depth, statements })
+    }
+}
+
+impl Tactics for Auto {
+    fn get_name(&self) -> String {
+        "auto".to_string()
+    }
+
+    fn get_desc(&self) -> String {
+        "A tactics which searches for a combination of the given statements proving the goal.".to_string()
+    }
+
+    fn check(&self, scope: &mut CheckScope) {
+        for s in &self.statements {
+            if let AutoStatement::Statement(s) = s {
+                s.check(scope);
+            }
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+        context.enter(&format!("Auto {}", DisplayPair(context.goal(), &context.db)));
+        let mut candidates = vec![];
+        for s in &self.statements {
+            match s {
+                AutoStatement::Statement(s) => {
+                    let label = s.evaluate(context)?;
+                    let (formula, hyps) = context.get_theorem_formulas(label).ok_or(TacticsError::UnknownLabel(label, Some(s.location().clone())))?;
+                    candidates.push((label, formula, hyps));
+                },
+                AutoStatement::Prefix(prefix) => {
+                    candidates.extend(context.statements(|statement| as_str(&statement.name).starts_with(prefix.as_str())));
+                },
+            }
+        }
+        let budget = context.auto_budget().unwrap_or(DEFAULT_BUDGET);
+        let search = Search { candidates, nodes: Cell::new(0), budget };
+        let context = &*context;
+        for depth in 0..=self.depth {
+            // The proofs found are passed on, in backtracking mode the search goes on when the continuation fails
+            let mut outcome = None;
+            let result = search.prove(context, context.goal(), depth, &mut |step, sub_context| {
+                match try_alternative(context, step.clone(), sub_context.work_variables(), then) {
+                    Some(result) => {
+                        outcome = Some(result);
+                        Ok(step)
+                    },
+                    None => Err(TacticsError::NoMatchFound),
+                }
+            });
+            if let Some(result) = outcome {
+                context.exit(&format!("Auto found a proof at depth {}", depth));
+                return result;
+            }
+            if let Err(TacticsError::BudgetExhausted(nodes)) = result {
+                context.exit(&format!("Auto gave up after {} goals", nodes));
+                return Err(TacticsError::BudgetExhausted(nodes));
+            }
+        }
+        context.exit("Auto: No proof found");
+        Err(TacticsError::NoMatchFound)
+    }
+}
+
+/// Whether the search shall stop with the given result, rather than try the next alternative
+fn is_final(result: &TacticsResult) -> bool {
+    matches!(result, Ok(_) | Err(TacticsError::BudgetExhausted(_)))
+}
+
+/// The state of a proof search by the `auto` tactics
+struct Search {
+    candidates: Vec<(Label, Formula, Hypotheses)>,
+    nodes: Cell<usize>,
+    budget: usize,
+}
+
+impl Search {
+    /// Find proofs for the goal, applying at most `depth` nested candidate statements,
+    /// and pass them to `then` until it succeeds.
+    /// Variables appearing only in the hypotheses of a candidate are replaced by work variables,
+    /// which are bound when matching the hypotheses and subgoals of the context.
+    fn prove(&self, context: &Context, goal: &Formula, depth: usize, then: &mut Found) -> TacticsResult {
+        self.nodes.set(self.nodes.get() + 1);
+        if self.nodes.get() > self.budget {
+            return Err(TacticsError::BudgetExhausted(self.budget));
+        }
+        let goal = context.resolve(goal);
+        for (label, hyp) in context.hypotheses().iter() {
+            if let Some(result) = Self::try_match(context, hyp, &goal, ProofStep::hyp(*label, hyp.clone()), then) {
+                return result;
+            }
+        }
+        for (hyp, step) in context.subgoals().iter() {
+            if let Some(result) = Self::try_match(context, hyp, &goal, step.clone(), then) {
+                return result;
+            }
+        }
+        if depth == 0 {
+            return Err(TacticsError::NoMatchFound);
+        }
+        for (label, formula, hyps) in &self.candidates {
+            let mut subst = Substitutions::new();
+            if goal.unify(formula, &mut subst).is_err() {
+                continue;
+            }
+            let mut sub_context = context.clone();
+            let floating = context.db.floating_hypotheses(*label).unwrap_or_default();
+            let free = floating.into_iter().filter(|variable| subst.get(*variable).is_none()).collect::<Vec<_>>();
+            if free.into_iter().try_for_each(|variable| {
+                subst.insert(variable, sub_context.new_work_variable(variable)?);
+                Ok::<_, TacticsError>(())
+            }).is_err() {
+                continue;
+            }
+            let result = self.prove_hypotheses(&sub_context, (*label, &goal, hyps, &subst), vec![], depth - 1, then);
+            if is_final(&result) {
+                return result;
+            }
+        }
+        Err(TacticsError::NoMatchFound)
+    }
+
+    /// Pass the given step to `then` if the hypothesis matches the goal, possibly binding work variables.
+    /// Returns the result if the search shall stop there.
+    fn try_match(context: &Context, hyp: &Formula, goal: &Formula, step: ProofStep, then: &mut Found) -> Option<TacticsResult> {
+        let result = if goal == hyp {
+            then(step, context)
+        } else {
+            let bindings = context.unify_work_variables(hyp, goal)?;
+            let mut sub_context = context.clone();
+            sub_context.bind_work_variables(&bindings);
+            then(step, &sub_context)
+        };
+        if is_final(&result) { Some(result) } else { None }
+    }
+
+    /// Prove the hypotheses of the applied statement in order, starting after the ones already proven by `steps`,
+    /// and pass the resulting step to `then`.
+    fn prove_hypotheses(&self, context: &Context, apply: (Label, &Formula, &Hypotheses, &Substitutions), steps: Vec<ProofStep>, depth: usize, then: &mut Found) -> TacticsResult {
+        let (label, goal, hyps, subst) = apply;
+        match hyps.get(steps.len()) {
+            None => then(ProofStep::apply(label, steps.into_boxed_slice(), goal.clone(), Box::new(subst.clone())), context),
+            Some((_, hyp)) => self.prove(context, &hyp.substitute(subst), depth, &mut |step, sub_context| {
+                let mut steps = steps.clone();
+                steps.push(step);
+                self.prove_hypotheses(sub_context, apply, steps, depth, then)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Settings;
+    use crate::error::Error;
+    use crate::tactics::TacticsError;
+    use crate::test_support::{prove, script};
+
+    #[test]
+    fn depth() {
+        let (script, _file) = script("");
+        assert!(matches!(script.parse_tactics("{ auto deep ~ax-mp }"), Err(Error::InvalidNumber { found, .. }) if found == "deep"));
+        // The first hypothesis of `bt` is a decoy, which the search tries first
+        assert!(prove(&script, "bt", "{ auto 1 ~ax-mp }").unwrap().is_complete());
+        assert!(prove(&script, "bt", "{ auto 0 ~ax-mp }").is_err());
+    }
+
+    #[test]
+    fn budget() {
+        let (mut script, _file) = script("");
+        script.set_settings(Settings { auto_budget: Some(1), ..Settings::default() });
+        // The goal is considered at depth 0, then the budget is exhausted at depth 1
+        assert!(matches!(prove(&script, "bt", "{ auto 1 ~ax-mp }"), Err(TacticsError::BudgetExhausted(1))));
+    }
+}
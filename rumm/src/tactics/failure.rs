@@ -0,0 +1,146 @@
+use annotate_snippets::display_list::FormatOptions;
+use annotate_snippets::snippet::{Annotation, AnnotationType, Snippet};
+use metamath_rs::Formula;
+use crate::error::Location;
+use crate::lang::{Db, DisplayPair};
+use crate::tactics::TacticsError;
+use typed_arena::Arena;
+
+/// A level of a failure tree: a tactics which failed, the goal it was working on, and why it failed.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub tactics: String,
+    pub goal: Formula,
+    pub location: Location,
+    pub cause: TacticsError,
+}
+
+impl TacticsError {
+    /// The failure of all the given alternatives.
+    /// Only the failure of the alternative which went the deepest, the first one among them, is kept,
+    /// so that failure trees stay linear during long searches.
+    pub fn all_failed(causes: impl IntoIterator<Item = TacticsError>) -> Self {
+        let mut count = 0;
+        let mut deepest: Option<(usize, TacticsError)> = None;
+        for cause in causes {
+            count += 1;
+            let depth = cause.failure_path().len();
+            if deepest.as_ref().map_or(true, |(deepest, _)| depth > *deepest) {
+                deepest = Some((depth, cause));
+            }
+        }
+        TacticsError::AllFailed { count, deepest: deepest.map(|(_, cause)| Box::new(cause)) }
+    }
+
+    /// The levels of the failure tree leading to the deepest failing goal.
+    /// When several alternatives failed, the one which went the deepest is followed, it is the only one kept.
+    pub fn failure_path(&self) -> Vec<&Failure> {
+        match self {
+            TacticsError::Failed(failure) => {
+                let mut path = vec![&**failure];
+                path.extend(failure.cause.failure_path());
+                path
+            },
+            TacticsError::AllFailed { deepest: Some(cause), .. } => cause.failure_path(),
+            _ => vec![],
+        }
+    }
+
+    /// The error at the origin of the failure, at the bottom of the failure path
+    pub fn root_cause(&self) -> &TacticsError {
+        match self.failure_path().last() {
+            Some(failure) => &failure.cause,
+            None => self,
+        }
+    }
+
+    /// A description of the error, for the user
+    pub fn message(&self, db: &Db) -> String {
+        match self {
+            TacticsError::Skipped => "The proof is incomplete".to_string(),
+            TacticsError::UnknownTactics(name, _) => format!("Unknown tactics {}", name),
+            TacticsError::UnificationFailed => "The goal does not match".to_string(),
+            TacticsError::NoMatchFound => "No match found".to_string(),
+            TacticsError::WrongParameterCount(expected, found, _) => format!("Expected {} parameters, found {}", expected, found),
+            TacticsError::WrongHypCount(tactics, hyps, _) => format!("{} sub-tactics given for a statement with {} hypotheses", tactics, hyps),
+            TacticsError::WrongTypecode(source, target, label) => format!("Cannot convert typecode {} into {} for {}", DisplayPair(source, db), DisplayPair(target, db), DisplayPair(label, db)),
+            TacticsError::UnknownLabel(label, _) => format!("Unknown label {}", DisplayPair(label, db)),
+            TacticsError::UnknownTheorem(name, _) => format!("Theorem {} was not added to the database", name),
+            TacticsError::UnprovenTheorem(name, _) => format!("Theorem {} is not a lemma proven so far", name),
+            TacticsError::UnknownFormulaVariable(id, _) => format!("Unknown formula variable {}", id),
+            TacticsError::UnknownTacticsVariable(id, _) => format!("Unknown tactics variable {}", id),
+            TacticsError::UnknownLabelVariable(id, _) => format!("Unknown theorem variable {}", id),
+            TacticsError::UnknownSubstitutionVariable(id, _) => format!("Unknown substitution variable {}", id),
+            TacticsError::MissingSubstitution(step, variable) => format!("No substitution for {} in step {}", DisplayPair(variable, db), DisplayPair(step, db)),
+            TacticsError::InvalidStep(step, reason) => format!("In step {}: {}", DisplayPair(step, db), reason),
+            TacticsError::NoWorkVariable(variable) => format!("No work variable left for {}", DisplayPair(variable, db)),
+            TacticsError::BudgetExhausted(nodes) => format!("Search abandoned after {} goals", nodes),
+            TacticsError::Failed(failure) => format!("{} failed on {}", failure.tactics, DisplayPair(&failure.goal, db)),
+            TacticsError::AllFailed { count: 0, .. } => "No alternative succeeded".to_string(),
+            TacticsError::AllFailed { count, .. } => format!("All {} alternatives failed", count),
+        }
+    }
+
+    /// Render the failure tree, with the goal and tactics at each level, and the deepest failing goal
+    pub fn to_snippet<'a>(&self, title: String, db: &Db, arena: &'a Arena<String>) -> Snippet<'a> {
+        let path = self.failure_path();
+        let root_cause = self.root_cause();
+        let mut slices: Vec<_> = path.iter()
+            .map(|failure| failure.location.to_slice(format!("{} failed on {}", failure.tactics, DisplayPair(&failure.goal, db)), arena))
+            .collect();
+        if let Some(location) = root_cause.location() {
+            slices.push(location.to_slice(root_cause.message(db), arena));
+        }
+        let mut footer = vec![];
+        if let Some(failure) = path.last() {
+            footer.push(Annotation {
+                id: None,
+                label: Some(arena.alloc(format!("Deepest failing goal: {}", DisplayPair(&failure.goal, db)))),
+                annotation_type: AnnotationType::Note,
+            });
+        }
+        footer.push(Annotation {
+            id: None,
+            label: Some(arena.alloc(format!("Cause: {}", root_cause.message(db)))),
+            annotation_type: AnnotationType::Note,
+        });
+        Snippet {
+            title: Some(Annotation {
+                id: None,
+                label: Some(arena.alloc(title)),
+                annotation_type: AnnotationType::Error,
+            }),
+            footer,
+            slices,
+            opt: FormatOptions {
+                color: true,
+                ..FormatOptions::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang::DisplayPair;
+    use crate::tactics::TacticsError;
+    use crate::test_support::{prove, script};
+    use annotate_snippets::display_list::DisplayList;
+    use typed_arena::Arena;
+
+    #[test]
+    fn failure_tree() {
+        let (script, _file) = script("");
+        // Both alternatives fail: `ax-mp` on its second hypothesis, after binding `ph` to the decoy `ch`, and `ax-dn` right away
+        let error = prove(&script, "bt", "{ try { apply ~ax-mp ! ! } { apply ~ax-dn ! } }").unwrap_err();
+        let path = error.failure_path();
+        assert_eq!(path.iter().map(|failure| failure.tactics.as_str()).collect::<Vec<_>>(), ["try", "apply", "!"]);
+        assert!(matches!(path[0].cause, TacticsError::AllFailed { count: 2, .. }));
+        assert!(DisplayPair(&path[2].goal, script.db()).to_string().ends_with("( ch -> ps )"));
+        assert!(matches!(error.root_cause(), TacticsError::NoMatchFound));
+        let arena = Arena::new();
+        let rendered = DisplayList::from(error.to_snippet("No proof found for bt".to_string(), script.db(), &arena)).to_string();
+        assert!(rendered.contains("Deepest failing goal: "));
+        assert!(rendered.contains("Cause: No match found"));
+    }
+}
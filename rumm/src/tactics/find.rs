@@ -3,88 +3,114 @@ use metamath_rs::formula::Substitutions;
 use metamath_rs::Formula;
 use crate::lang::DisplayPair;
 use crate::lang::FormulaExpression;
+use crate::lang::IndexedStatement;
 use crate::lang::TacticsExpression;
-use crate::context::Context;
+use crate::check::CheckScope;
+use crate::context::{Context, WorkVariables};
 use crate::error::Result;
 use crate::lang::ProofStep;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, find_first, try_alternative, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 /// Generic trait for finding a theorem among all database statements to prove the goal.
-trait FilterFind {
-    fn filter(&self, is_axiom: bool, label: &[u8]) -> bool;
+trait FilterFind: Sync {
+    fn filter(&self, statement: &IndexedStatement) -> bool;
 }
 
 impl dyn FilterFind {
-    fn find(&self, context: &mut Context, tactics1: &TacticsExpression, formula: &FormulaExpression, tactics2: &TacticsExpression) -> TacticsResult {
+    fn find(&self, context: &mut Context, tactics1: &TacticsExpression, formula: &FormulaExpression, tactics2: &TacticsExpression, then: &mut Continuation) -> TacticsResult {
         let target = formula.evaluate(context)?.substitute(context.variables());
         context.enter(&format!("Find {}", DisplayPair(&target, &context.db)));
+        // The failures of the alternatives tried, the deepest one is reported if none succeeds
+        let mut errors = vec![];
         for (label, formula) in context.clone().hypotheses().iter() {
             context.message(&format!("Trying {}", DisplayPair(formula, &context.db)));
-            if let Ok(step) = self.check_match(context, &target, &*formula, tactics2, |_subst| {
+            match self.check_match(context, &target, &*formula, tactics2, |_subst| {
                 Ok(ProofStep::hyp(*label, formula.clone()))
             }) {
-                context.exit(&format!("Matched hypothesis {}", DisplayPair(formula, &context.db)));
-                return Ok(step);
+                Ok((step, work_variables)) => {
+                    if let Some(result) = try_alternative(context, step, &work_variables, then) {
+                        context.exit(&format!("Matched hypothesis {}", DisplayPair(formula, &context.db)));
+                        return result;
+                    }
+                },
+                Err(e) => errors.push(e),
             }
         }
         for (hyp, step) in context.clone().subgoals().iter() {
             context.message(&format!("Trying {}", DisplayPair(hyp, &context.db)));
-            if let Ok(step) = self.check_match(context, &target, &*hyp, tactics2, |_subst| {
+            match self.check_match(context, &target, &*hyp, tactics2, |_subst| {
                 Ok(step.clone())
             }) {
-                context.exit(&format!("Matched subgoal {}", DisplayPair(hyp, &context.db)));
-                return Ok(step);
+                Ok((step, work_variables)) => {
+                    if let Some(result) = try_alternative(context, step, &work_variables, then) {
+                        context.exit(&format!("Matched subgoal {}", DisplayPair(hyp, &context.db)));
+                        return result;
+                    }
+                },
+                Err(e) => errors.push(e),
             }
         }
-        for (label, formula, hyps) in context.clone().statements(|is_axiom, label| self.filter(is_axiom, label)) {
-            if let Ok(step) = self.check_match(context, &target, &formula, &tactics2,|subst| {
-                context.message(&format!("Found match with {}", DisplayPair(&label, &context.db)));
-                // context.message(&format!("  subst:{}", DisplayPair(subst, &context.db)));
+        let statements = context.candidate_statements(&target, |statement| self.filter(statement));
+        // Candidates may be checked several times in parallel mode, only their last failure is kept
+        let failures = Mutex::new(HashMap::new());
+        let mut start = 0;
+        while let Some((index, (step, work_variables))) = find_first(context, &statements, start, |context, (label, formula, hyps)| {
+            let result = self.check_match(context, &target, formula, tactics2, |subst| {
+                context.message(&format!("Found match with {}", DisplayPair(label, &context.db)));
                 let mut substeps = vec![];
-                let mut failed = false;
                 for (_hyp_label, hyp_formula) in hyps.iter() {
                     let sub_goal = hyp_formula.substitute(&subst);
                     let mut sub_context = context.with_goal(sub_goal).with_variables(&subst);
-                    if let Ok(sub_step) = tactics1.execute(&mut sub_context) {
-                        substeps.push(sub_step);
-                    } else {
-                        failed = true;
-                    }
+                    substeps.push(tactics1.execute(&mut sub_context)?);
                 }
-                if failed { return Err(TacticsError::NoMatchFound); };
                 context.message("Unification success");
                 let subgoal = formula.substitute(&subst);
                 context.message(&format!("  subgoal = {}", DisplayPair(&subgoal, &context.db)));
                 let mut subgoal_subst = Substitutions::new();
                 subgoal.unify(&formula, &mut subgoal_subst)?;
                 Ok(ProofStep::apply(
-                    label,
+                    *label,
                     substeps.into_boxed_slice(),
                     subgoal.clone(),
                     Box::new(subgoal_subst.clone()),
                 ))
-            }) {
+            });
+            match result {
+                Ok(found) => Some(found),
+                Err(e) => {
+                    failures.lock().unwrap().insert(*label, e);
+                    None
+                },
+            }
+        }) {
+            if let Some(result) = try_alternative(context, step, &work_variables, then) {
                 context.exit("Find Successful!");
-                return Ok(step);
+                return result;
             }
+            start = index + 1;
         }
+        let mut failures = failures.into_inner().unwrap();
+        errors.extend(statements.iter().filter_map(|(label, _, _)| failures.remove(label)));
         context.exit("Find: No match found");
-        Err(TacticsError::NoMatchFound)
+        if errors.is_empty() { Err(TacticsError::NoMatchFound) } else { Err(TacticsError::all_failed(errors)) }
     }
 
-    fn check_match<F>(&self, context: &Context, target: &Formula, formula: &Formula, tactics2: &TacticsExpression, make_proof_step: F) -> TacticsResult
+    fn check_match<F>(&self, context: &Context, target: &Formula, formula: &Formula, tactics2: &TacticsExpression, make_proof_step: F) -> TacticsResult<(ProofStep, WorkVariables)>
         where F: Fn(&Box<Substitutions>) -> TacticsResult {
         let mut subst = Substitutions::new();
         target.unify(&formula, & mut subst)?;
         let step1 = make_proof_step(&Box::new(subst.clone()))?;
         let mut context2 = context.with_variables(&subst);
         context2.add_subgoal(step1.result().clone(), step1);
-        Ok(tactics2.execute(&mut context2)?)
+        let step = tactics2.execute(&mut context2)?;
+        Ok((step, context2.work_variables().clone()))
     }
 }
 
@@ -98,11 +124,13 @@ pub struct Find {
 
 impl Display for Find {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Find \n")?;
+        fmt.write_str("{ find ")?;
         self.tactics1.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.formula.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.tactics2.format(fmt, db)?;
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -121,7 +149,7 @@ impl Parse for Find {
 }
 
 impl FilterFind for Find {
-    fn filter(&self, _is_axiom: bool, _label: &[u8]) -> bool {
+    fn filter(&self, _statement: &IndexedStatement) -> bool {
         true // No filtering
     }
 }
@@ -135,8 +163,18 @@ impl Tactics for Find {
         "A tactics which searches for a theorem matching the given formula.".to_string()
     }
     
+    fn check(&self, scope: &mut CheckScope) {
+        self.tactics1.check(scope);
+        self.formula.check(scope);
+        self.tactics2.check(scope);
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
-        <dyn FilterFind>::find(self, context, &self.tactics1, &self.formula, &self.tactics2)
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+        <dyn FilterFind>::find(self, context, &self.tactics1, &self.formula, &self.tactics2, then)
     }
 }
 
@@ -150,11 +188,13 @@ pub struct FindDf {
 
 impl Display for FindDf {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ FindDf \n")?;
+        fmt.write_str("{ finddf ")?;
         self.tactics1.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.formula.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.tactics2.format(fmt, db)?;
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -173,8 +213,8 @@ impl Parse for FindDf {
 }
 
 impl FilterFind for FindDf {
-    fn filter(&self, is_axiom: bool, label: &[u8]) -> bool {
-        is_axiom && as_str(label).starts_with("df-")
+    fn filter(&self, statement: &IndexedStatement) -> bool {
+        statement.is_axiom && as_str(&statement.name).starts_with("df-")
     }
 }
 
@@ -187,7 +227,17 @@ impl Tactics for FindDf {
         "A tactics which searches for a definition matching the given formula.".to_string()
     }
     
+    fn check(&self, scope: &mut CheckScope) {
+        self.tactics1.check(scope);
+        self.formula.check(scope);
+        self.tactics2.check(scope);
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
-        <dyn FilterFind>::find(self, context, &self.tactics1, &self.formula, &self.tactics2)
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+        <dyn FilterFind>::find(self, context, &self.tactics1, &self.formula, &self.tactics2, then)
     }
 }
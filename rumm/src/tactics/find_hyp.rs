@@ -3,12 +3,13 @@ use metamath_rs::Formula;
 use crate::lang::DisplayPair;
 use crate::lang::FormulaExpression;
 use crate::lang::TacticsExpression;
-use crate::context::Context;
+use crate::check::CheckScope;
+use crate::context::{Context, WorkVariables};
 use crate::error::Result;
 use crate::lang::ProofStep;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, try_alternative, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
@@ -23,10 +24,11 @@ pub struct FindHyp {
 
 impl Display for FindHyp {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Find! \n")?;
+        fmt.write_str("{ findhyp ")?;
         self.formula.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.tactics.format(fmt, db)?;
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -51,7 +53,16 @@ impl Tactics for FindHyp {
         "A tactics which searches for a hypothesis matching the given formula.".to_string()
     }
 
+    fn check(&self, scope: &mut CheckScope) {
+        self.formula.check(scope);
+        self.tactics.check(scope);
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         let target = self.formula.evaluate(context)?.substitute(context.variables());
         context.enter(&format!("Find! {}", DisplayPair(&target, &context.db)));
         for (label, formula) in context.clone().hypotheses().iter() {
@@ -59,9 +70,11 @@ impl Tactics for FindHyp {
             match self.check_match(context, &target, &*formula, |_subst| {
                 Ok(ProofStep::hyp(*label, formula.clone()))
             }) {
-                Ok(step) => {
-                    context.exit(&format!("Matched hypothesis {}", DisplayPair(formula, &context.db)));
-                    return Ok(step);
+                Ok((step, work_variables)) => {
+                    if let Some(result) = try_alternative(context, step, &work_variables, then) {
+                        context.exit(&format!("Matched hypothesis {}", DisplayPair(formula, &context.db)));
+                        return result;
+                    }
                 },
                 Err(e) => {
                     context.message(&format!("{:?}", e));
@@ -73,9 +86,11 @@ impl Tactics for FindHyp {
             match self.check_match(context, &target, &*hyp, |_subst| {
                 Ok(step.clone())
             }) {
-                Ok(step) => {
-                    context.exit(&format!("Matched subgoal {}", DisplayPair(hyp, &context.db)));
-                    return Ok(step);
+                Ok((step, work_variables)) => {
+                    if let Some(result) = try_alternative(context, step, &work_variables, then) {
+                        context.exit(&format!("Matched subgoal {}", DisplayPair(hyp, &context.db)));
+                        return result;
+                    }
                 },
                 Err(e) => {
                     context.message(&format!("{:?}", e));
@@ -88,13 +103,16 @@ impl Tactics for FindHyp {
 }
 
 impl FindHyp {
-    fn check_match<F>(&self, context: &Context, target: &Formula, formula: &Formula, make_proof_step: F) -> TacticsResult
+    fn check_match<F>(&self, context: &Context, target: &Formula, formula: &Formula, make_proof_step: F) -> TacticsResult<(ProofStep, WorkVariables)>
         where F: Fn(&Box<Substitutions>) -> TacticsResult {
         let mut subst = Substitutions::new();
         formula.unify(&target, & mut subst)?;
         let step1 = make_proof_step(&Box::new(subst.clone()))?;
         let mut context2 = context.with_variables(&subst);
+        // The work variables of the goal matched by the hypothesis are bound, like with the `!` tactics
+        context2.bind_work_variables(&context.work_variable_bindings(&subst));
         context2.add_subgoal(step1.result().clone(), step1);
-        Ok(self.tactics.execute(&mut context2)?)
+        let step = self.tactics.execute(&mut context2)?;
+        Ok((step, context2.work_variables().clone()))
     }
 }
@@ -3,7 +3,7 @@ use crate::error::Result;
 use crate::lang::ProofStep;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, try_alternative, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
@@ -36,23 +36,75 @@ impl Tactics for Hypothesis {
     }
 
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         context.enter("!");
         for (label, hyp) in context.hypotheses().iter() {
             if context.goal().eq(hyp) {
-                context.exit("Matched hypothesis!");
-                return Ok(ProofStep::hyp(
-                    *label,
-                    context.goal().clone(),
-                ));
+                let step = ProofStep::hyp(*label, context.goal().clone());
+                if let Some(result) = try_alternative(context, step, context.work_variables(), then) {
+                    context.exit("Matched hypothesis!");
+                    return result;
+                }
             }
         }
         for (hyp, step) in context.subgoals().iter() {
             if context.goal().eq(hyp) {
-                context.exit("Matched subgoal!");
-                return Ok(step.clone());
+                if let Some(result) = try_alternative(context, step.clone(), context.work_variables(), then) {
+                    context.exit("Matched subgoal!");
+                    return result;
+                }
+            }
+        }
+        // The goal may contain work variables, which can be bound to match a hypothesis.
+        // The ones already equal to the goal were tried above.
+        for (label, hyp) in context.hypotheses().iter().filter(|(_, hyp)| !context.goal().eq(hyp)) {
+            if let Some(bindings) = context.unify_work_variables(hyp, context.goal()) {
+                let mut sub_context = context.clone();
+                sub_context.bind_work_variables(&bindings);
+                let step = ProofStep::hyp(*label, sub_context.goal().clone());
+                if let Some(result) = try_alternative(context, step, sub_context.work_variables(), then) {
+                    context.exit("Matched hypothesis!");
+                    return result;
+                }
+            }
+        }
+        for (hyp, step) in context.subgoals().iter().filter(|(hyp, _)| !context.goal().eq(hyp)) {
+            if let Some(bindings) = context.unify_work_variables(hyp, context.goal()) {
+                let mut sub_context = context.clone();
+                sub_context.bind_work_variables(&bindings);
+                if let Some(result) = try_alternative(context, step.clone(), sub_context.work_variables(), then) {
+                    context.exit("Matched subgoal!");
+                    return result;
+                }
             }
         }
         context.exit("Hypothesis failed");
         Err(TacticsError::NoMatchFound)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{prove, script, statement};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn binds_work_variables() {
+        let (script, _file) = script("");
+        // The work variable for `ph` of `ax-mp` is bound by the first `!`, to the first hypothesis, which is a decoy
+        assert!(prove(&script, "bt", "{ apply ~ax-mp ! ! }").is_err());
+        // Without the decoy, it is bound to `ph`, and the proof found has no work variables left
+        let (label, goal, hypotheses) = statement(&script, "bt");
+        let hypotheses = hypotheses[1..].to_vec().into_boxed_slice();
+        let tactics = script.parse_tactics("{ apply ~ax-mp ! ! }").unwrap();
+        let step = script.prove(&tactics, goal, hypotheses, Some(label)).unwrap();
+        assert!(step.is_complete());
+        assert!(step.check(script.db()).is_ok());
+        let mut variables = vec![];
+        step.collect_variables(script.db(), &mut variables, &mut BTreeSet::new());
+        assert_eq!(variables, ["ps", "ph"]);
+    }
+}
@@ -1,13 +1,14 @@
 use crate::lang::TacticsExpression;
 use metamath_rs::Formula;
 use metamath_rs::formula::Substitutions;
-use crate::lang::FormulaExpression;
+use crate::lang::{format_formula, FormulaExpression};
+use crate::check::CheckScope;
 use crate::context::Context;
 use crate::error::Result;
 use crate::lang::DisplayPair;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
@@ -21,13 +22,15 @@ pub struct Match {
 
 impl Display for Match {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Match\n")?;
+        fmt.write_str("{ match ")?;
         self.target.format(fmt, db)?;
         for (f, t) in &self.matches {
-            f.format(fmt, db)?;
+            fmt.write_str(" ")?;
+            format_formula(f, fmt, db)?;
+            fmt.write_str(" ")?;
             t.format(fmt, db)?;
         }
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -44,14 +47,25 @@ impl Parse for Match {
 
 impl Tactics for Match {
     fn get_name(&self) -> String {
-        "try".to_string()
+        "match".to_string()
     }
 
     fn get_desc(&self) -> String {
-        "A tactics which tries a list of tactics until one of them produces a proof.".to_string()
+        "A tactics which matches a formula with a list of formulas, and applies the tactics of the first match which succeeds.".to_string()
+    }
+
+    fn check(&self, scope: &mut CheckScope) {
+        self.target.check(scope);
+        for (_, t) in &self.matches {
+            t.check(scope);
+        }
     }
 
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         context.enter("Match");
         let model = self.target.evaluate(&context)?.substitute(context.variables());
         // match &self.target {
@@ -63,6 +77,7 @@ impl Tactics for Match {
         // };
         context.message(&format!("Target {}", DisplayPair(&model, &context.db)));
 //        context.message(format!("{}", context.debug_formula(&model)));
+        let mut errors = vec![];
         for m in self.matches.iter() {
             let m2 = m.0.substitute(context.variables());
             context.message(&format!("Trying {}", DisplayPair(&m2, &context.db)));
@@ -75,18 +90,28 @@ impl Tactics for Match {
                     DisplayPair(&m2, &context.db)
                 ));
                 let mut sub_context = context.with_variables(&subst);
-                match m.1.execute(&mut sub_context) {
+                let mut continued = false;
+                match m.1.execute_then(&mut sub_context, &mut |step, sub_context| { continued = true; then(step, sub_context) }) {
                     Ok(step) => {
                         context.exit("Match successful");
                         return Ok(step);
                     },
                     Err(e) => {
                         context.message(format!("{:?}", e).as_str());
+                        if continued {
+                            if !context.backtrack() {
+                                context.exit("-- Match failed --");
+                                return Err(e);
+                            }
+                        } else {
+                            errors.push(e);
+                        }
                     },
                 }
             }
         }
         context.exit("-- Match failed --");
-        Err(TacticsError::NoMatchFound)
+        // Without any matching formula, there is no alternative to report
+        if errors.is_empty() { Err(TacticsError::NoMatchFound) } else { Err(TacticsError::all_failed(errors)) }
     }
 }
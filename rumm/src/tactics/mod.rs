@@ -1,8 +1,11 @@
 //! Tactics for the Rumm proof assistant
 
 mod apply;
+mod auto;
+mod failure;
 mod hypothesis;
 mod r#match;
+mod registry;
 mod skipped;
 mod subgoal;
 mod r#try;
@@ -10,13 +13,17 @@ mod find_hyp;
 mod find;
 mod use_script_tactics;
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
 pub use apply::Apply;
+pub use auto::Auto;
+pub use failure::Failure;
 pub use hypothesis::Hypothesis;
 use metamath_rs::Label;
 use metamath_rs::formula::TypeCode;
 use metamath_rs::formula::UnificationError;
 pub use r#match::Match;
+pub use registry::{TacticsConstructor, TacticsRegistry};
 pub use r#try::Try;
 pub use skipped::Skipped;
 pub use subgoal::Subgoal;
@@ -25,27 +32,66 @@ pub use find::Find;
 pub use find::FindDf;
 pub use use_script_tactics::UseScriptTactics;
 
-use crate::context::Context;
+use crate::check::CheckScope;
+use crate::context::{Context, WorkVariables};
+use crate::error::Location;
 use crate::lang::Display;
 use crate::lang::ProofStep;
 use crate::parser::Parse;
 
 pub type TacticsResult<T = ProofStep> = std::result::Result<T, TacticsError>;
 
-#[derive(Debug)]
+/// A continuation, to which tactics pass the proofs they find.
+/// The context passed along carries the work variables allocated and bound for that proof.
+pub type Continuation<'a> = dyn FnMut(ProofStep, &mut Context) -> TacticsResult + 'a;
+
+#[derive(Clone, Debug)]
 pub enum TacticsError {
     Skipped,
-    UnknownTactics(String),
+    UnknownTactics(String, Location),
     UnificationFailed,
     NoMatchFound,
-    WrongParameterCount(usize, usize),
-    WrongHypCount(usize, usize),
+    WrongParameterCount(usize, usize, Location),
+    WrongHypCount(usize, usize, Location),
     WrongTypecode(TypeCode, TypeCode, Label),
-    UnknownLabel(Label),
-    UnknownFormulaVariable(String),
-    UnknownTacticsVariable(String),
-    UnknownLabelVariable(String),
-    UnknownSubstitutionVariable(String),
+    /// An unknown label, along with where it was given in the script, if it was
+    UnknownLabel(Label, Option<Location>),
+    /// A theorem stated in the script, which is not in the database
+    UnknownTheorem(String, Location),
+    /// A theorem stated in the script, which is not a lemma proven so far
+    UnprovenTheorem(String, Location),
+    UnknownFormulaVariable(String, Location),
+    UnknownTacticsVariable(String, Location),
+    UnknownLabelVariable(String, Location),
+    UnknownSubstitutionVariable(String, Location),
+    MissingSubstitution(Label, Label),
+    InvalidStep(Label, String),
+    NoWorkVariable(Label),
+    BudgetExhausted(usize),
+    /// A tactics failed on a goal, for the given cause
+    Failed(Box<Failure>),
+    /// All the alternatives tried failed, only the failure of the one which went the deepest is kept
+    AllFailed { count: usize, deepest: Option<Box<TacticsError>> },
+}
+
+impl TacticsError {
+    /// Where the error occurred in the script, if known
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            TacticsError::UnknownTactics(_, location) => Some(location),
+            TacticsError::WrongParameterCount(_, _, location) => Some(location),
+            TacticsError::WrongHypCount(_, _, location) => Some(location),
+            TacticsError::UnknownLabel(_, location) => location.as_ref(),
+            TacticsError::UnknownTheorem(_, location) => Some(location),
+            TacticsError::UnprovenTheorem(_, location) => Some(location),
+            TacticsError::UnknownFormulaVariable(_, location) => Some(location),
+            TacticsError::UnknownTacticsVariable(_, location) => Some(location),
+            TacticsError::UnknownLabelVariable(_, location) => Some(location),
+            TacticsError::UnknownSubstitutionVariable(_, location) => Some(location),
+            TacticsError::Failed(failure) => Some(&failure.location),
+            _ => None,
+        }
+    }
 }
 
 impl From<UnificationError> for TacticsError {
@@ -56,12 +102,25 @@ impl From<UnificationError> for TacticsError {
 
 /// The trait implemented by all tactics.
 ///
-pub trait Tactics: Parse + Display {
+pub trait Tactics: Parse + Display + Send + Sync {
     fn get_name(&self) -> String;
     //fn arg_types(&self) ->
     fn get_desc(&self) -> String;
     fn execute(&self, context: &mut Context) -> TacticsResult;
 
+    /// Statically check the tactics and its sub-expressions, reporting the problems found to the scope.
+    /// Tactics without sub-expressions have nothing to check.
+    fn check(&self, _scope: &mut CheckScope) {}
+
+    /// Execute the tactics, and pass the proof found to the continuation `then`.
+    /// Tactics providing several alternative proofs override this, so that in backtracking mode,
+    /// they can try their next alternative when the continuation fails.
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
+        let mut sub_context = context.clone();
+        let step = self.execute(&mut sub_context)?;
+        then(step, &mut sub_context)
+    }
+
     /// Return a Arc to the tactics.
     fn into_arc(self) -> Arc<dyn Tactics>
     where
@@ -70,3 +129,58 @@ pub trait Tactics: Parse + Display {
         Arc::new(self)
     }
 }
+
+/// Execute a tactics through its continuation, keeping the first proof found,
+/// and taking over the work variables bound for that proof.
+pub fn execute_first<T: Tactics + ?Sized>(tactics: &T, context: &mut Context) -> TacticsResult {
+    let mut work_variables = None;
+    let step = tactics.execute_then(context, &mut |step, sub_context| {
+        work_variables = Some(sub_context.work_variables().clone());
+        Ok(step)
+    })?;
+    if let Some(work_variables) = work_variables {
+        context.commit_work_variables(&work_variables);
+    }
+    Ok(step)
+}
+
+/// Find the first of the candidates, starting at index `start`, for which `check` succeeds,
+/// and return its index along with the result. `check` is given the context in which to evaluate the candidate.
+/// In parallel mode, candidates are checked concurrently, but the first success in the order of the candidates is still the one returned.
+/// Each candidate then traces into its own log, and the logs of the candidates up to the one returned are appended in order,
+/// so that the traces are the same as when the candidates are checked one after the other.
+pub fn find_first<T: Sync, R: Send>(context: &Context, candidates: &[T], start: usize, check: impl Fn(&Context, &T) -> Option<R> + Sync + Send) -> Option<(usize, R)> {
+    if context.is_parallel() {
+        let logs: Vec<Mutex<Option<String>>> = candidates[start..].iter().map(|_| Mutex::new(None)).collect();
+        let found = candidates[start..].par_iter().enumerate().find_map_first(|(index, candidate)| {
+            let candidate_context = context.with_new_log();
+            let result = check(&candidate_context, candidate);
+            *logs[index].lock().unwrap() = Some(candidate_context.log());
+            result.map(|result| (start + index, result))
+        });
+        let end = found.as_ref().map_or(logs.len(), |(index, _)| index - start + 1);
+        for log in &logs[..end] {
+            if let Some(log) = log.lock().unwrap().take() {
+                context.append_log(&log);
+            }
+        }
+        found
+    } else {
+        candidates[start..].iter().enumerate().find_map(|(index, candidate)| check(context, candidate).map(|result| (start + index, result)))
+    }
+}
+
+/// Pass one alternative proof to the continuation.
+/// Returns `None` if the continuation failed and the next alternative shall be tried,
+/// which is only the case in backtracking mode, within the backtracking limit.
+pub fn try_alternative(context: &Context, step: ProofStep, work_variables: &WorkVariables, then: &mut Continuation) -> Option<TacticsResult> {
+    let mut sub_context = context.clone();
+    sub_context.commit_work_variables(work_variables);
+    match then(step, &mut sub_context) {
+        Err(e) if context.backtrack() => {
+            context.message(&format!("Backtracking after {:?}", e));
+            None
+        },
+        result => Some(result),
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::Result;
+use crate::parser::Parser;
+use crate::tactics::{Apply, Auto, Find, FindDf, FindHyp, Match, Subgoal, Tactics, Try, UseScriptTactics};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Parse a tactics, once its name has been read, up to and including its closing brace
+pub type TacticsConstructor = fn(&mut Parser) -> Result<Arc<dyn Tactics>>;
+
+/// The tactics which can be written within braces `{ name ... }`, by name.
+/// The default registry holds the built-in tactics, other ones can be registered from Rust.
+#[derive(Clone)]
+pub struct TacticsRegistry {
+    constructors: HashMap<String, TacticsConstructor>,
+}
+
+impl Default for TacticsRegistry {
+    fn default() -> Self {
+        let mut registry = TacticsRegistry { constructors: HashMap::new() };
+        registry.register::<UseScriptTactics>("use");
+        registry.register::<Subgoal>("subgoal");
+        registry.register::<Apply>("apply");
+        registry.register::<Auto>("auto");
+        registry.register::<Try>("try");
+        registry.register::<Match>("match");
+        registry.register::<Find>("find");
+        registry.register::<FindDf>("finddf");
+        registry.register::<FindHyp>("findhyp");
+        registry
+    }
+}
+
+fn construct<T: Tactics + 'static>(parser: &mut Parser) -> Result<Arc<dyn Tactics>> {
+    Ok(T::parse(parser)?.into_arc())
+}
+
+impl TacticsRegistry {
+    /// Register a tactics under the given name, replacing any tactics already registered with that name
+    pub fn register<T: Tactics + 'static>(&mut self, name: &str) {
+        self.constructors.insert(name.to_string(), construct::<T>);
+    }
+
+    /// The constructor of the tactics registered under the given name
+    pub fn get(&self, name: &str) -> Option<TacticsConstructor> {
+        self.constructors.get(name).copied()
+    }
+
+    /// The names of all the tactics registered, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.constructors.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TacticsRegistry;
+    use crate::error::Error;
+    use crate::lang::TacticsExpression;
+    use crate::parser::Parser;
+    use crate::tactics::Skipped;
+    use std::sync::Arc;
+
+    #[test]
+    fn builtin_names() {
+        let registry = TacticsRegistry::default();
+        let mut names: Vec<&str> = registry.names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["apply", "auto", "find", "finddf", "findhyp", "match", "subgoal", "try", "use"]);
+        assert!(registry.get("apply").is_some());
+        assert!(registry.get("skip").is_none());
+    }
+
+    #[test]
+    fn register() {
+        let mut registry = TacticsRegistry::default();
+        registry.register::<Skipped>("skip");
+        let mut parser = Parser::with_registry("<test>".to_string(), "{ skip }", Arc::new(registry));
+        match parser.parse_tactics() {
+            Ok(TacticsExpression::Constant(tactics, _)) => assert_eq!(tactics.get_name(), "?"),
+            _ => panic!("the registered tactics shall be parsed"),
+        }
+    }
+
+    #[test]
+    fn unknown_name() {
+        let mut parser = Parser::with_registry("<test>".to_string(), "{ skip }", Arc::new(TacticsRegistry::default()));
+        assert!(matches!(parser.parse_tactics(), Err(Error::UnknownTacticsName(_, name)) if name == "skip"));
+    }
+}
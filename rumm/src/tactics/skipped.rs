@@ -1,9 +1,9 @@
 use crate::context::Context;
 use crate::error::Result;
+use crate::lang::ProofStep;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
 use crate::tactics::Tactics;
-use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
 
@@ -36,7 +36,8 @@ impl Tactics for Skipped {
 
     fn execute(&self, context: &mut Context) -> TacticsResult {
         context.enter("Skip");
+        context.hole();
         context.exit("Skipped!");
-        Err(TacticsError::Skipped)
+        Ok(ProofStep::skipped(context.goal().clone()))
     }
 }
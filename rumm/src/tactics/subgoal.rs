@@ -1,4 +1,5 @@
 use crate::lang::{TacticsExpression, FormulaExpression};
+use crate::check::CheckScope;
 use crate::context::Context;
 use crate::error::Result;
 use crate::lang::{Db, Display};
@@ -32,11 +33,13 @@ impl Parse for Subgoal {
 
 impl Display for Subgoal {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Subgoal \n")?;
+        fmt.write_str("{ subgoal ")?;
         self.tactics1.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.subgoal.format(fmt, db)?;
+        fmt.write_str(" ")?;
         self.tactics2.format(fmt, db)?;
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -49,14 +52,25 @@ impl Tactics for Subgoal {
         "A tactics which allows to insert a subgoal, prove it or assume it, and then move forward with the rest of the proof.".to_string()
     }
 
+    fn check(&self, scope: &mut CheckScope) {
+        self.tactics1.check(scope);
+        self.subgoal.check(scope);
+        self.tactics2.check(scope);
+    }
+
     fn execute(&self, mut context: &mut Context) -> TacticsResult {
         context.enter("Subgoal");
-        let subgoal = self.subgoal.evaluate(context)?.substitute(context.variables());
+        let subgoal = context.resolve(&self.subgoal.evaluate(context)?.substitute(context.variables()));
         let mut context1 = context.with_goal(subgoal.clone());
         match self.tactics1.execute(&mut context1) {
             Ok(step1) => {
-                context.add_subgoal(subgoal, step1);
+                let work_variables = context.work_variables().clone();
+                context.commit_work_variables(context1.work_variables());
+                context.add_subgoal(context.resolve(&subgoal), step1);
                 let res = self.tactics2.execute(&mut context);
+                if res.is_err() {
+                    context.commit_work_variables(&work_variables);
+                }
                 context.exit("Subgoal complete");
                 res
             },
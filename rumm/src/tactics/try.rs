@@ -1,12 +1,14 @@
 use crate::lang::TacticsExpression;
+use crate::check::CheckScope;
 use crate::context::Context;
 use crate::error::Result;
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser, OptionalTactics};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, find_first, try_alternative, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
+use std::sync::Mutex;
 
 /// A tactics which tries a list of tactics until one of them produces a proof.
 ///
@@ -16,11 +18,12 @@ pub struct Try {
 
 impl Display for Try {
     fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Try \n")?;
+        fmt.write_str("{ try")?;
         for t in &self.tactics {
+            fmt.write_str(" ")?;
             t.format(fmt, db)?;
         }
-        fmt.write_str("}\n")
+        fmt.write_str(" }")
     }
 }
 
@@ -43,20 +46,94 @@ impl Tactics for Try {
         "A tactics which tries a list of tactics until one of them produces a proof.".to_string()
     }
 
+    fn check(&self, scope: &mut CheckScope) {
+        for t in &self.tactics {
+            t.check(scope);
+        }
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         context.enter("Try");
+        if context.is_parallel() {
+            // Sub-tactics are run concurrently, each providing its first proof only
+            let errors = Mutex::new(vec![]);
+            let mut start = 0;
+            while let Some((index, (step, work_variables))) = find_first(context, &self.tactics, start, |context, t| {
+                let mut sub_context = context.clone();
+                match t.execute(&mut sub_context) {
+                    Ok(step) => Some((step, sub_context.work_variables().clone())),
+                    Err(e) => {
+                        context.message(format!("{:?}",e).as_str());
+                        errors.lock().unwrap().push(e);
+                        None
+                    },
+                }
+            }) {
+                if let Some(result) = try_alternative(context, step, &work_variables, then) {
+                    context.exit("Try Successful");
+                    return result;
+                }
+                start = index + 1;
+            }
+            context.exit("-- Try Failed --");
+            return Err(TacticsError::all_failed(errors.into_inner().unwrap()));
+        }
+        let mut errors = vec![];
         for t in &self.tactics {
-            match t.execute(context) {
+            // Whether the tactics found a proof, which was then refused by the continuation
+            let mut continued = false;
+            match t.execute_then(context, &mut |step, sub_context| { continued = true; then(step, sub_context) }) {
                 Ok(step) => {
                     context.exit("Try Successful");
                     return Ok(step);
                 },
                 Err(e) => {
                     context.message(format!("{:?}",e).as_str());
+                    if continued {
+                        if !context.backtrack() {
+                            context.exit("-- Try Failed --");
+                            return Err(e);
+                        }
+                    } else {
+                        errors.push(e);
+                    }
                 },
             }
         }
         context.exit("-- Try Failed --");
-        Err(TacticsError::NoMatchFound)
+        Err(TacticsError::all_failed(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Settings;
+    use crate::test_support::{prove, script};
+
+    /// The work variable for `ph` of `ax-mp` is bound to `ch` by `findhyp`, and to the hypotheses in order by `!`:
+    /// only the second hypothesis of `bt` lets the second `!` succeed.
+    const TACTICS: &str = "{ apply ~ax-mp { try { findhyp $ ch $ ! } ! } ! }";
+
+    #[test]
+    fn backtracking() {
+        let (mut script, _file) = script("");
+        // Without backtracking, the first proof found for the first hypothesis is kept
+        assert!(prove(&script, "bt", TACTICS).is_err());
+        // The alternatives of `findhyp` all fail, then `try` goes on with `!`, whose second alternative succeeds
+        script.set_settings(Settings { backtrack_limit: Some(10), ..Settings::default() });
+        assert!(prove(&script, "bt", TACTICS).unwrap().is_complete());
+    }
+
+    #[test]
+    fn backtracking_limit() {
+        let (mut script, _file) = script("");
+        for limit in [0, 2] {
+            script.set_settings(Settings { backtrack_limit: Some(limit), ..Settings::default() });
+            assert!(prove(&script, "bt", TACTICS).is_err());
+        }
     }
 }
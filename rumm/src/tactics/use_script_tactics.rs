@@ -1,9 +1,10 @@
 use crate::lang::Expression;
+use crate::check::CheckScope;
 use crate::context::Context;
-use crate::error::Result;
+use crate::error::{Error, Location, Result};
 use crate::lang::{Db, Display};
 use crate::parser::{Parse, Parser};
-use crate::tactics::Tactics;
+use crate::tactics::{execute_first, Continuation, Tactics};
 use crate::tactics::TacticsError;
 use crate::tactics::TacticsResult;
 use core::fmt::Formatter;
@@ -12,22 +13,28 @@ use core::fmt::Formatter;
 ///
 pub struct UseScriptTactics {
     name: String,
+    location: Location,
     parameters: Vec<Expression>,
 }
 
 impl Parse for UseScriptTactics {
     fn parse(parser: &mut Parser) -> Result<Self> {
         let name = parser.parse_identifier()?;
+        let location = parser.location();
         let parameters = parser.parse_parameters()?;
-        Ok(UseScriptTactics { name, parameters })
+        Ok(UseScriptTactics { name, location, parameters })
     }
 }
 
 impl Display for UseScriptTactics {
-    fn format(&self, fmt: &mut Formatter, _db: &Db) -> std::result::Result<(), std::fmt::Error> {
-        fmt.write_str("{ Match \n")?;
+    fn format(&self, fmt: &mut Formatter, db: &Db) -> std::result::Result<(), std::fmt::Error> {
+        fmt.write_str("{ use ")?;
         fmt.write_str(&self.name)?;
-        fmt.write_str("}\n")
+        for p in &self.parameters {
+            fmt.write_str(" ")?;
+            p.format(fmt, db)?;
+        }
+        fmt.write_str(" }")
     }
 }
 
@@ -40,18 +47,87 @@ impl Tactics for UseScriptTactics {
         "A tactics for calling a tactics defined in the Rumm script.".to_string()
     }
 
+    fn check(&self, scope: &mut CheckScope) {
+        for p in &self.parameters {
+            p.check(scope);
+        }
+        match scope.tactics_definition(&self.name) {
+            Some(definition) => {
+                if definition.parameter_definition.len() != self.parameters.len() {
+                    scope.error(Error::WrongParameterCount {
+                        location: self.location.clone(),
+                        name: self.name.clone(),
+                        expected: definition.parameter_definition.len(),
+                        found: self.parameters.len(),
+                    });
+                }
+                for (index, (parameter, parameter_definition)) in self.parameters.iter().zip(&definition.parameter_definition).enumerate() {
+                    if !parameter_definition.accepts(parameter) {
+                        scope.error(Error::WrongParameterKind {
+                            location: self.location.clone(),
+                            name: self.name.clone(),
+                            index: index + 1,
+                            expected: parameter_definition.kind(),
+                            found: parameter.kind(),
+                        });
+                    }
+                }
+            },
+            None => scope.error(Error::UnknownScriptTactics { location: self.location.clone(), name: self.name.clone() }),
+        }
+    }
+
     fn execute(&self, context: &mut Context) -> TacticsResult {
+        execute_first(self, context)
+    }
+
+    fn execute_then(&self, context: &mut Context, then: &mut Continuation) -> TacticsResult {
         context.enter(&format!("Use {}", self.name));
         if let Some(tactics_definition) = context.clone().get_tactics_definition(self.name.clone())
         {
             let mut sub_context = context.without_variables();
-            tactics_definition.add_variables(&mut sub_context, &self.parameters)?;
-            let res = tactics_definition.execute(&mut sub_context);
+            tactics_definition.add_variables(&mut sub_context, &self.parameters, &self.location)?;
+            // In backtracking mode, the cache is not used: a cached proof would leave out the other alternatives,
+            // and a failure may be due to the backtracking limit being reached
+            let cache = if context.is_backtracking() { None } else { context.cache().cloned() };
+            let cache_key = sub_context.cache_key(&self.name);
+            if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+                if let Some(result) = cache.get(key) {
+                    context.exit(&format!("{} found in cache", self.name));
+                    return match result {
+                        Ok(step) => {
+                            // The goals left unproven are recorded, as if the tactics had been executed
+                            for goal in step.skipped_goals() {
+                                sub_context.with_goal(goal).hole();
+                            }
+                            then(step, &mut context.clone())
+                        },
+                        Err(e) => Err(e),
+                    };
+                }
+            }
+            // Remember the first proof found, and whether the failure comes from the continuation
+            let mut found = None;
+            let mut continued = false;
+            let res = tactics_definition.execute_then(&mut sub_context, &mut |step, sub_context| {
+                if !continued && sub_context.work_variables().is_empty() {
+                    found = Some(step.clone());
+                }
+                continued = true;
+                then(step, sub_context)
+            });
+            if let (Some(cache), Some(key)) = (cache, cache_key) {
+                match (found, &res) {
+                    (Some(step), _) => cache.insert(key, Ok(step)),
+                    (None, Err(e)) if !continued => cache.insert(key, Err(e.clone())),
+                    _ => {},
+                }
+            }
             context.exit(&format!("{} complete", self.name));
             res
         } else {
             context.exit(&format!("{} failed", self.name));
-            Err(TacticsError::UnknownTactics(self.name.to_string()))
+            Err(TacticsError::UnknownTactics(self.name.to_string(), self.location.clone()))
         }
     }
 }
@@ -0,0 +1,121 @@
+//! Helpers shared by the tests: a small database, and temporary files which are removed when dropped,
+//! even if the test panics.
+
+use crate::lang::{Db, Hypotheses};
+use crate::script::Script;
+use crate::tactics::{TacticsRegistry, TacticsResult};
+use metamath_rs::{Formula, Label};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A small database, with work variables, axioms, and theorems to prove:
+/// `id` is proven by its hypothesis, `bt` needs `ax-mp`, and its first hypothesis is a decoy.
+/// The variable `ta` is only declared within a closed block.
+pub const DATABASE: &str = "$c |- wff ( ) -> -. $.
+$v ph ps ch &W1 &W2 $.
+wph $f wff ph $.
+wps $f wff ps $.
+wch $f wff ch $.
+ww1 $f wff &W1 $.
+ww2 $f wff &W2 $.
+wi $a wff ( ph -> ps ) $.
+wn $a wff -. ph $.
+ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+${
+  dn.1 $e |- ph $.
+  ax-dn $a |- -. -. ph $.
+$}
+${
+  mp.1 $e |- ph $.
+  mp.2 $e |- ( ph -> ps ) $.
+  ax-mp $a |- ps $.
+$}
+${
+  $v ta $.
+  wta $f wff ta $.
+$}
+${
+  id.1 $e |- ph $.
+  id $p |- ph $= ? $.
+$}
+${
+  bt.1 $e |- ch $.
+  bt.2 $e |- ph $.
+  bt.3 $e |- ( ph -> ps ) $.
+  bt $p |- ps $= ? $.
+$}
+";
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file or a directory in the temporary directory, with a name unique to the test, removed when dropped
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    fn new(name: &str) -> Self {
+        let unique = COUNTER.fetch_add(1, Ordering::SeqCst);
+        TempPath(std::env::temp_dir().join(format!("rumm-{}-{}-{}", std::process::id(), unique, name)))
+    }
+
+    /// A file with the given text
+    pub fn file(name: &str, text: &str) -> Self {
+        let path = TempPath::new(name);
+        fs::write(&path.0, text).unwrap();
+        path
+    }
+
+    /// A directory with the given files, whose names are relative to the directory
+    pub fn dir(name: &str, files: &[(&str, &str)]) -> Self {
+        let path = TempPath::new(name);
+        for (filename, text) in files {
+            let file = path.0.join(filename);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, text).unwrap();
+        }
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn filename(&self) -> String {
+        self.0.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // The test result matters more than the cleanup, which is not checked
+        let _ = if self.0.is_dir() { fs::remove_dir_all(&self.0) } else { fs::remove_file(&self.0) };
+    }
+}
+
+/// Load a database with the given text. Its file is kept until the returned path is dropped,
+/// since the database source is read again when proofs are verified or written.
+pub fn load_db(text: &str) -> (Db, TempPath) {
+    let file = TempPath::file("database.mm", text);
+    (Db::load(&file.filename()).unwrap(), file)
+}
+
+/// Parse a script using the test database, which the script shall not load itself
+pub fn script(text: &str) -> (Script, TempPath) {
+    let (db, file) = load_db(DATABASE);
+    let script = Script::from_str_with_db("<test>".to_string(), text, db, Arc::new(TacticsRegistry::default())).unwrap();
+    (script, file)
+}
+
+/// The label, conclusion and essential hypotheses of the given theorem of the database
+pub fn statement(script: &Script, name: &str) -> (Label, Formula, Hypotheses) {
+    let label = script.db().get_theorem_label(name.to_string()).unwrap();
+    let (goal, hypotheses) = script.db().get_theorem_formulas(label).unwrap();
+    (label, goal, hypotheses)
+}
+
+/// Run the given tactics on the given theorem of the database
+pub fn prove(script: &Script, name: &str, tactics: &str) -> TacticsResult {
+    let (label, goal, hypotheses) = statement(script, name);
+    script.prove(&script.parse_tactics(tactics).unwrap(), goal, hypotheses, Some(label))
+}
@@ -0,0 +1,61 @@
+//! Leveled tracing of the proof search
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// How traces are filtered and formatted
+#[derive(Clone, Copy)]
+pub struct TraceSettings {
+    /// The most detailed level traced
+    pub level: LevelFilter,
+    /// Whether traces are written as JSON lines, for tools, instead of plain text
+    pub json: bool,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        TraceSettings { level: LevelFilter::Info, json: false }
+    }
+}
+
+impl TraceSettings {
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    /// Format one trace line, including the final line break
+    pub fn format(&self, level: Level, target: &str, depth: usize, message: &str) -> String {
+        if self.json {
+            format!("{}\n", serde_json::json!({
+                "level": level.as_str(),
+                "target": target,
+                "depth": depth,
+                "message": message,
+            }))
+        } else {
+            format!("{:indent$}{message}\n", "", indent = depth)
+        }
+    }
+}
+
+/// The logger for the messages not related to a single proof, written to the standard error output
+struct Logger(TraceSettings);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprint!("{}", self.0.format(record.level(), record.target(), 0, &record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger for the `log` macros
+pub fn init(settings: TraceSettings) {
+    if log::set_logger(Box::leak(Box::new(Logger(settings)))).is_ok() {
+        log::set_max_level(settings.level);
+    }
+}